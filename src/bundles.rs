@@ -1,34 +1,69 @@
 use crate::{
-    dungeon_crawl::{EnemyAI, GameData, Health, Item, Name, Player},
+    dungeon_crawl::{
+        Awareness, CombatStats, EnemyAI, GameData, Health, Item, Name, Player, Speed,
+        StatusEffects, ViewRadius,
+    },
+    items::ItemTemplate,
+    monsters::MonsterTemplate,
     world_map::BlocksMovement,
 };
-use bevy::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+/// Creates sprite materials. When there is no asset server (e.g. in the headless
+/// app) it hands out default handles instead, so the same bundles can be spawned.
+#[derive(SystemParam)]
+pub struct SpriteMaterials<'a> {
+    asset_server: Option<Res<'a, AssetServer>>,
+    materials: Option<ResMut<'a, Assets<ColorMaterial>>>,
+}
+
+impl<'a> SpriteMaterials<'a> {
+    pub fn get(&mut self, texture: &str, color: Color) -> Handle<ColorMaterial> {
+        match (&self.asset_server, &mut self.materials) {
+            (Some(asset_server), Some(materials)) => materials.add(ColorMaterial {
+                texture: Some(asset_server.load(texture)),
+                color,
+            }),
+            _ => Default::default(),
+        }
+    }
+}
 
 #[derive(Bundle)]
 pub struct EnemyBundle {
     #[bundle]
     sprite: SpriteBundle,
-    _e: EnemyAI,
+    ai: EnemyAI,
     _bm: BlocksMovement,
     health: Health,
     name: Name,
+    view_radius: ViewRadius,
+    awareness: Awareness,
+    stats: CombatStats,
+    speed: Speed,
+    statuses: StatusEffects,
 }
 
 impl EnemyBundle {
-    pub fn orc(asset_server: &AssetServer, materials: &mut ResMut<Assets<ColorMaterial>>) -> Self {
+    pub fn new(template: &MonsterTemplate, materials: &mut SpriteMaterials) -> Self {
         Self {
             sprite: SpriteBundle {
-                material: materials.add(ColorMaterial {
-                    texture: Some(asset_server.load("orc-head.png")),
-                    color: Color::hex("DA0037").unwrap(),
-                }),
+                material: materials.get(&template.texture, template.color()),
                 transform: Transform::from_xyz(0.0, 0.0, 1.0),
                 ..Default::default()
             },
-            _e: EnemyAI,
+            ai: EnemyAI {
+                ai: template.ai,
+                flees_at: template.flees_at,
+            },
             _bm: BlocksMovement,
-            health: Health::new(3, 3),
-            name: Name(String::from("orc")),
+            health: Health::new(template.hp, template.hp),
+            name: Name(template.name.clone()),
+            view_radius: ViewRadius(template.view_radius),
+            awareness: Awareness::Unaware,
+            stats: template.stats,
+            speed: Speed(template.speed),
+            statuses: StatusEffects::default(),
         }
     }
 }
@@ -40,26 +75,30 @@ pub struct PlayerBundle {
     player: Player,
     health: Health,
     name: Name,
+    view_radius: ViewRadius,
+    stats: CombatStats,
+    speed: Speed,
+    statuses: StatusEffects,
 }
 
 impl PlayerBundle {
-    pub fn new(
-        asset_server: &AssetServer,
-        materials: &mut ResMut<Assets<ColorMaterial>>,
-        data: &GameData,
-    ) -> Self {
+    pub fn new(materials: &mut SpriteMaterials, data: &GameData) -> Self {
         Self {
             sprite: SpriteBundle {
-                material: materials.add(ColorMaterial {
-                    texture: Some(asset_server.load("hooded-figure.png")),
-                    color: Color::hex("EDEDED").unwrap(),
-                }),
+                material: materials.get("hooded-figure.png", Color::hex("EDEDED").unwrap()),
                 transform: Transform::from_xyz(0.0, 0.0, 1.0),
                 ..Default::default()
             },
             player: Player,
             health: data.previous_hp.unwrap_or(Health::new(8, 8)),
             name: Name(String::from("player")),
+            view_radius: ViewRadius(5),
+            stats: CombatStats {
+                power: 1,
+                ..Default::default()
+            },
+            speed: Speed(Speed::NORMAL),
+            statuses: data.previous_statuses.clone(),
         }
     }
 }
@@ -73,145 +112,15 @@ pub struct ItemBundle {
 }
 
 impl ItemBundle {
-    pub fn item(
-        item: Item,
-        asset_server: &AssetServer,
-        materials: &mut ResMut<Assets<ColorMaterial>>,
-    ) -> Self {
-        match item {
-            Item::HealthPotion => Self::health_potion(asset_server, materials),
-            Item::ScrollOfLightning => Self::scroll_of_lightning(asset_server, materials),
-            Item::ScrollOfParalysis => Self::scroll_of_paralysis(asset_server, materials),
-            Item::ScrollOfFireball => Self::scroll_of_fireball(asset_server, materials),
-            Item::Sword => Self::sword(asset_server, materials),
-            Item::WarAxe => Self::war_axe(asset_server, materials),
-            Item::Armor => Self::armor(asset_server, materials),
-        }
-    }
-
-    pub fn health_potion(
-        asset_server: &AssetServer,
-        materials: &mut ResMut<Assets<ColorMaterial>>,
-    ) -> Self {
-        Self {
-            sprite: SpriteBundle {
-                material: materials.add(ColorMaterial {
-                    texture: Some(asset_server.load("potion-ball.png")),
-                    color: Color::hex("DA0037").unwrap(),
-                }),
-                transform: Transform::from_xyz(0.0, 0.0, 1.0),
-                ..Default::default()
-            },
-            item: Item::HealthPotion,
-            name: Name(String::from("health potion")),
-        }
-    }
-
-    pub fn scroll_of_lightning(
-        asset_server: &AssetServer,
-        materials: &mut ResMut<Assets<ColorMaterial>>,
-    ) -> Self {
-        Self {
-            sprite: SpriteBundle {
-                material: materials.add(ColorMaterial {
-                    texture: Some(asset_server.load("scroll-unfurled.png")),
-                    color: Color::hex("EDEDED").unwrap(),
-                }),
-                transform: Transform::from_xyz(0.0, 0.0, 1.0),
-                ..Default::default()
-            },
-            item: Item::ScrollOfLightning,
-            name: Name(String::from("scroll of lightning")),
-        }
-    }
-
-    pub fn scroll_of_paralysis(
-        asset_server: &AssetServer,
-        materials: &mut ResMut<Assets<ColorMaterial>>,
-    ) -> Self {
-        Self {
-            sprite: SpriteBundle {
-                material: materials.add(ColorMaterial {
-                    texture: Some(asset_server.load("scroll-unfurled.png")),
-                    color: Color::hex("EDEDED").unwrap(),
-                }),
-                transform: Transform::from_xyz(0.0, 0.0, 1.0),
-                ..Default::default()
-            },
-            item: Item::ScrollOfParalysis,
-            name: Name(String::from("scroll of paralysis")),
-        }
-    }
-
-    pub fn scroll_of_fireball(
-        asset_server: &AssetServer,
-        materials: &mut ResMut<Assets<ColorMaterial>>,
-    ) -> Self {
-        Self {
-            sprite: SpriteBundle {
-                material: materials.add(ColorMaterial {
-                    texture: Some(asset_server.load("scroll-unfurled.png")),
-                    color: Color::hex("EDEDED").unwrap(),
-                }),
-                transform: Transform::from_xyz(0.0, 0.0, 1.0),
-                ..Default::default()
-            },
-            item: Item::ScrollOfFireball,
-            name: Name(String::from("scroll of fireball")),
-        }
-    }
-
-    pub fn war_axe(
-        asset_server: &AssetServer,
-        materials: &mut ResMut<Assets<ColorMaterial>>,
-    ) -> Self {
-        Self {
-            sprite: SpriteBundle {
-                material: materials.add(ColorMaterial {
-                    texture: Some(asset_server.load("battle-axe.png")),
-                    color: Color::hex("EDEDED").unwrap(),
-                }),
-                transform: Transform::from_xyz(0.0, 0.0, 1.0),
-                ..Default::default()
-            },
-            item: Item::WarAxe,
-            name: Name(String::from("war axe")),
-        }
-    }
-
-    pub fn sword(
-        asset_server: &AssetServer,
-        materials: &mut ResMut<Assets<ColorMaterial>>,
-    ) -> Self {
-        Self {
-            sprite: SpriteBundle {
-                material: materials.add(ColorMaterial {
-                    texture: Some(asset_server.load("gladius.png")),
-                    color: Color::hex("EDEDED").unwrap(),
-                }),
-                transform: Transform::from_xyz(0.0, 0.0, 1.0),
-                ..Default::default()
-            },
-            item: Item::Sword,
-            name: Name(String::from("sword")),
-        }
-    }
-
-    pub fn armor(
-        asset_server: &AssetServer,
-        materials: &mut ResMut<Assets<ColorMaterial>>,
-    ) -> Self {
+    pub fn new(template: &ItemTemplate, materials: &mut SpriteMaterials) -> Self {
         Self {
             sprite: SpriteBundle {
-                material: materials.add(ColorMaterial {
-                    texture: Some(asset_server.load("breastplate.png")),
-                    color: Color::hex("EDEDED").unwrap(),
-                }),
+                material: materials.get(&template.texture, template.color()),
                 transform: Transform::from_xyz(0.0, 0.0, 1.0),
                 ..Default::default()
             },
-            item: Item::Armor,
-            name: Name(String::from("armor")),
+            item: Item(template.name.clone()),
+            name: Name(template.name.clone()),
         }
     }
 }
@@ -0,0 +1,455 @@
+#[cfg(not(feature = "headless"))]
+mod screen;
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt};
+#[cfg(not(feature = "headless"))]
+use {crate::storage, ron::ser::PrettyConfig};
+
+#[cfg(not(feature = "headless"))]
+pub use screen::ControlsPlugin;
+
+/// Everything the player can do with a key or a mouse button.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Action {
+    MoveNorth,
+    MoveSouth,
+    MoveWest,
+    MoveEast,
+    MoveNorthWest,
+    MoveNorthEast,
+    MoveSouthWest,
+    MoveSouthEast,
+    /// Passes a turn.
+    Wait,
+    /// Passes turns until healed or interrupted.
+    Rest,
+    /// Walks to the closest unexplored place until something interesting happens.
+    Explore,
+    /// Walks to the tile under the mouse.
+    Travel,
+    /// Walks next to the stairs down.
+    TravelToStairs,
+    PickUp,
+    /// Selects the inventory slot, counting from 0.
+    UseSlot(usize),
+    Look,
+    Messages,
+    /// Scrolls the message history a page back.
+    PageUp,
+    /// Scrolls the message history a page forward.
+    PageDown,
+    NextTarget,
+    Confirm,
+    Throw,
+    /// Closes the open screen, or leaves the game.
+    Cancel,
+}
+
+impl Action {
+    pub const MOVES: [Action; 8] = [
+        Action::MoveNorth,
+        Action::MoveSouth,
+        Action::MoveWest,
+        Action::MoveEast,
+        Action::MoveNorthWest,
+        Action::MoveNorthEast,
+        Action::MoveSouthWest,
+        Action::MoveSouthEast,
+    ];
+
+    /// Grid step of a move action.
+    pub fn direction(&self) -> Option<IVec2> {
+        match self {
+            Action::MoveNorth => Some(IVec2::new(0, 1)),
+            Action::MoveSouth => Some(IVec2::new(0, -1)),
+            Action::MoveWest => Some(IVec2::new(-1, 0)),
+            Action::MoveEast => Some(IVec2::new(1, 0)),
+            Action::MoveNorthWest => Some(IVec2::new(-1, 1)),
+            Action::MoveNorthEast => Some(IVec2::new(1, 1)),
+            Action::MoveSouthWest => Some(IVec2::new(-1, -1)),
+            Action::MoveSouthEast => Some(IVec2::new(1, -1)),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(feature = "headless"))]
+    /// Only read while aiming an item.
+    fn aiming_only(&self) -> bool {
+        matches!(self, Action::Confirm | Action::Throw)
+    }
+
+    #[cfg(not(feature = "headless"))]
+    /// Only read on the dungeon itself, not on the screens opened over it.
+    fn dungeon_only(&self) -> bool {
+        matches!(
+            self,
+            Action::Wait
+                | Action::Rest
+                | Action::Explore
+                | Action::Travel
+                | Action::TravelToStairs
+                | Action::PickUp
+        )
+    }
+
+    #[cfg(not(feature = "headless"))]
+    /// Actions that are never read on the same screen can have the same binding,
+    /// like Left click to travel and to confirm a target.
+    pub fn can_share_binding(&self, other: Action) -> bool {
+        self.aiming_only() && other.dungeon_only() || self.dungeon_only() && other.aiming_only()
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::MoveNorth => write!(f, "Move north"),
+            Action::MoveSouth => write!(f, "Move south"),
+            Action::MoveWest => write!(f, "Move west"),
+            Action::MoveEast => write!(f, "Move east"),
+            Action::MoveNorthWest => write!(f, "Move north-west"),
+            Action::MoveNorthEast => write!(f, "Move north-east"),
+            Action::MoveSouthWest => write!(f, "Move south-west"),
+            Action::MoveSouthEast => write!(f, "Move south-east"),
+            Action::Wait => write!(f, "Wait"),
+            Action::Rest => write!(f, "Rest"),
+            Action::Explore => write!(f, "Explore"),
+            Action::Travel => write!(f, "Travel"),
+            Action::TravelToStairs => write!(f, "Travel to stairs"),
+            Action::PickUp => write!(f, "Pick up"),
+            Action::UseSlot(slot) => write!(f, "Use item {}", slot + 1),
+            Action::Look => write!(f, "Look"),
+            Action::Messages => write!(f, "Messages"),
+            Action::PageUp => write!(f, "Page up"),
+            Action::PageDown => write!(f, "Page down"),
+            Action::NextTarget => write!(f, "Next target"),
+            Action::Confirm => write!(f, "Confirm"),
+            Action::Throw => write!(f, "Throw"),
+            Action::Cancel => write!(f, "Cancel"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Binding {
+    /// A key pressed without Shift.
+    Key(KeyCode),
+    /// A key pressed with Shift, like `>` which is Shift+Period.
+    Shift(KeyCode),
+    Mouse(MouseButton),
+}
+
+impl Binding {
+    fn key_name(key: KeyCode) -> String {
+        let name = format!("{:?}", key);
+        // Key1 is just 1
+        match name.strip_prefix("Key") {
+            Some(digit) if digit.len() == 1 => digit.to_string(),
+            _ => name,
+        }
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "{}", Self::key_name(*key)),
+            Binding::Shift(key) => write!(f, "Shift+{}", Self::key_name(*key)),
+            Binding::Mouse(button) => write!(f, "{:?} click", button),
+        }
+    }
+}
+
+/// Built-in sets of bindings. They only differ in the keys used for moving and waiting.
+// Only the controls screen picks presets other than the default.
+#[cfg_attr(feature = "headless", allow(dead_code))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    Wasd,
+    ViKeys,
+    Numpad,
+}
+
+impl Preset {
+    #[cfg(not(feature = "headless"))]
+    pub const ALL: [Preset; 3] = [Preset::Wasd, Preset::ViKeys, Preset::Numpad];
+
+    #[cfg(not(feature = "headless"))]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Preset::Wasd => "WASD",
+            Preset::ViKeys => "VI-KEYS",
+            Preset::Numpad => "NUMPAD",
+        }
+    }
+
+    pub fn bindings(&self) -> BTreeMap<Action, Vec<Binding>> {
+        use KeyCode::*;
+
+        // In the order of `Action::MOVES`
+        let moves = match self {
+            Preset::Wasd => [W, S, A, D, Q, E, Z, C],
+            Preset::ViKeys => [K, J, H, L, Y, U, B, N],
+            Preset::Numpad => [
+                Numpad8, Numpad2, Numpad4, Numpad6, Numpad7, Numpad9, Numpad1, Numpad3,
+            ],
+        };
+        // Every preset also moves straight with the arrows and diagonally with the keypad.
+        let shared = [Up, Down, Left, Right, Numpad7, Numpad9, Numpad1, Numpad3];
+
+        let mut bindings = BTreeMap::new();
+        for ((action, key), other) in Action::MOVES.iter().zip(moves).zip(shared) {
+            let mut keys = vec![Binding::Key(key)];
+            if other != key {
+                keys.push(Binding::Key(other));
+            }
+            bindings.insert(*action, keys);
+        }
+        for (slot, key) in [Key1, Key2, Key3, Key4, Key5].iter().enumerate() {
+            bindings.insert(Action::UseSlot(slot), vec![Binding::Key(*key)]);
+        }
+        let wait = match self {
+            Preset::Wasd => Space,
+            Preset::ViKeys => Period,
+            Preset::Numpad => Numpad5,
+        };
+        bindings.insert(Action::Wait, vec![Binding::Key(wait)]);
+        bindings.insert(Action::Rest, vec![Binding::Key(R)]);
+        bindings.insert(Action::Explore, vec![Binding::Key(O)]);
+        bindings.insert(Action::Travel, vec![Binding::Mouse(MouseButton::Left)]);
+        bindings.insert(Action::TravelToStairs, vec![Binding::Shift(Period)]);
+        bindings.insert(Action::PickUp, vec![Binding::Key(G)]);
+        bindings.insert(Action::Look, vec![Binding::Key(X)]);
+        bindings.insert(Action::Messages, vec![Binding::Key(M)]);
+        bindings.insert(Action::PageUp, vec![Binding::Key(PageUp)]);
+        bindings.insert(Action::PageDown, vec![Binding::Key(PageDown)]);
+        bindings.insert(Action::NextTarget, vec![Binding::Key(Tab)]);
+        let mut confirm = vec![Binding::Key(Return), Binding::Mouse(MouseButton::Left)];
+        if *self == Preset::Numpad {
+            confirm.push(Binding::Key(NumpadEnter));
+        }
+        bindings.insert(Action::Confirm, confirm);
+        bindings.insert(
+            Action::Throw,
+            vec![Binding::Key(T), Binding::Mouse(MouseButton::Right)],
+        );
+        bindings.insert(Action::Cancel, vec![Binding::Key(Escape)]);
+
+        bindings
+    }
+}
+
+/// What every action is bound to, and other ways to tune how the game is played.
+/// Kept in `controls.ron` on native builds and in `localStorage` in the browser.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Controls {
+    pub bindings: BTreeMap<Action, Vec<Binding>>,
+    /// Auto-explore also walks to the items it sees and picks them up.
+    #[serde(default)]
+    pub auto_pickup: bool,
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Self {
+            bindings: Preset::Wasd.bindings(),
+            auto_pickup: false,
+        }
+    }
+}
+
+impl Controls {
+    #[cfg(not(feature = "headless"))]
+    const STORAGE_NAME: &'static str = "controls.ron";
+
+    #[cfg(not(feature = "headless"))]
+    /// Actions missing from the stored file keep their default bindings.
+    pub fn load() -> Self {
+        let mut controls = Self::default();
+        if let Some(stored) =
+            storage::read(Self::STORAGE_NAME).and_then(|s| ron::from_str::<Controls>(&s).ok())
+        {
+            controls.bindings.extend(stored.bindings);
+            controls.auto_pickup = stored.auto_pickup;
+        }
+        controls
+    }
+
+    #[cfg(not(feature = "headless"))]
+    pub fn store(&self) {
+        // Pretty so it's easy to edit by hand.
+        let result = ron::ser::to_string_pretty(self, PrettyConfig::new())
+            .map_err(|e| e.to_string())
+            .and_then(|s| storage::write(Self::STORAGE_NAME, &s));
+        if let Err(err) = result {
+            error!("Couldn't save the controls: {}", err);
+        }
+    }
+
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], |b| b.as_slice())
+    }
+
+    #[cfg(not(feature = "headless"))]
+    /// The bindings of the action, e.g. "W, Up".
+    pub fn describe(&self, action: Action) -> String {
+        let bindings: Vec<String> = self
+            .bindings(action)
+            .iter()
+            .map(Binding::to_string)
+            .collect();
+        if bindings.is_empty() {
+            String::from("unbound")
+        } else {
+            bindings.join(", ")
+        }
+    }
+
+    #[cfg(not(feature = "headless"))]
+    /// Makes `binding` the only one of `action`, taking it away from the actions it
+    /// can't share it with.
+    pub fn rebind(&mut self, action: Action, binding: Binding) {
+        for (other, bindings) in self.bindings.iter_mut() {
+            if !action.can_share_binding(*other) {
+                bindings.retain(|b| *b != binding);
+            }
+        }
+        self.bindings.insert(action, vec![binding]);
+    }
+}
+
+/// Keyboard and mouse input seen through the player's bindings.
+#[derive(SystemParam)]
+pub struct ActionInput<'a> {
+    pub keys: ResMut<'a, Input<KeyCode>>,
+    pub buttons: ResMut<'a, Input<MouseButton>>,
+    pub controls: Res<'a, Controls>,
+}
+
+impl<'a> ActionInput<'a> {
+    pub fn just_pressed(&self, action: Action) -> bool {
+        let shift = is_shift_pressed(&self.keys);
+        self.controls
+            .bindings(action)
+            .iter()
+            .any(|binding| match binding {
+                Binding::Key(key) => !shift && self.keys.just_pressed(*key),
+                Binding::Shift(key) => shift && self.keys.just_pressed(*key),
+                Binding::Mouse(button) => self.buttons.just_pressed(*button),
+            })
+    }
+
+    /// One of the actions that were just pressed.
+    pub fn just_pressed_action(&self) -> Option<Action> {
+        self.controls
+            .bindings
+            .keys()
+            .copied()
+            .find(|action| self.just_pressed(*action))
+    }
+
+    /// States entered in this frame run right away and would see the press again.
+    pub fn reset(&mut self, action: Action) {
+        for binding in self.controls.bindings(action) {
+            match binding {
+                Binding::Key(key) | Binding::Shift(key) => self.keys.reset(*key),
+                Binding::Mouse(button) => self.buttons.reset(*button),
+            }
+        }
+    }
+
+    /// Like `reset`, for every key and button pressed this frame. Used when closing a
+    /// screen, so the one below can't act on a binding the two share.
+    pub fn reset_all(&mut self) {
+        let keys: Vec<KeyCode> = self.keys.get_just_pressed().copied().collect();
+        for key in keys {
+            self.keys.reset(key);
+        }
+        let buttons: Vec<MouseButton> = self.buttons.get_just_pressed().copied().collect();
+        for button in buttons {
+            self.buttons.reset(button);
+        }
+    }
+}
+
+fn is_shift_pressed(keys: &Input<KeyCode>) -> bool {
+    keys.pressed(KeyCode::LShift) || keys.pressed(KeyCode::RShift)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_preset_moves_diagonally_with_the_keypad() {
+        use KeyCode::*;
+        for preset in Preset::ALL {
+            let bindings = preset.bindings();
+            for (action, key) in Action::MOVES[4..]
+                .iter()
+                .zip([Numpad7, Numpad9, Numpad1, Numpad3])
+            {
+                assert!(
+                    bindings[action].contains(&Binding::Key(key)),
+                    "{} doesn't bind {:?} to {}",
+                    preset.name(),
+                    key,
+                    action
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn only_actions_of_different_screens_share_bindings() {
+        assert!(Action::Confirm.can_share_binding(Action::Travel));
+        assert!(Action::Rest.can_share_binding(Action::Throw));
+        assert!(!Action::Confirm.can_share_binding(Action::Throw));
+        assert!(!Action::Rest.can_share_binding(Action::Explore));
+        assert!(!Action::MoveNorth.can_share_binding(Action::Confirm));
+        assert!(!Action::Cancel.can_share_binding(Action::Travel));
+    }
+
+    #[test]
+    fn rebinding_takes_the_binding_from_other_actions() {
+        let mut controls = Controls::default();
+        controls.rebind(Action::Wait, Binding::Key(KeyCode::W));
+        assert_eq!(controls.bindings(Action::Wait), [Binding::Key(KeyCode::W)]);
+        assert!(!controls
+            .bindings(Action::MoveNorth)
+            .contains(&Binding::Key(KeyCode::W)));
+        assert!(controls
+            .bindings(Action::MoveNorth)
+            .contains(&Binding::Key(KeyCode::Up)));
+    }
+
+    #[test]
+    fn rebinding_keeps_bindings_that_can_be_shared() {
+        let mut controls = Controls::default();
+        let click = Binding::Mouse(MouseButton::Right);
+        controls.rebind(Action::Travel, click);
+        controls.rebind(Action::Confirm, click);
+        assert_eq!(controls.bindings(Action::Travel), [click]);
+        assert_eq!(controls.bindings(Action::Confirm), [click]);
+        controls.rebind(Action::Look, click);
+        assert!(controls.bindings(Action::Travel).is_empty());
+        assert_eq!(controls.describe(Action::Confirm), "unbound");
+    }
+
+    #[test]
+    fn presets_give_each_move_its_own_keys() {
+        for preset in Preset::ALL {
+            let bindings = preset.bindings();
+            let mut keys: Vec<Binding> = Action::MOVES
+                .iter()
+                .flat_map(|action| bindings[action].iter().copied())
+                .collect();
+            let count = keys.len();
+            keys.sort_by_key(|binding| format!("{:?}", binding));
+            keys.dedup();
+            assert_eq!(keys.len(), count, "{}", preset.name());
+        }
+    }
+}
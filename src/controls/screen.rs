@@ -0,0 +1,291 @@
+//! The controls screen, where bindings are changed one action at a time or by preset.
+
+use super::{is_shift_pressed, Action, Binding, Controls, Preset};
+use crate::AppState;
+use bevy::prelude::*;
+
+pub struct ControlsPlugin;
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(Controls::load())
+            .init_resource::<Rebinding>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::Controls).with_system(controls_ui_create.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Controls)
+                    .with_system(controls_interaction.system().label("controls"))
+                    .with_system(controls_ui_update.system().after("controls")),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Controls).with_system(controls_cleanup.system()),
+            );
+    }
+}
+
+pub struct ControlsCanvas;
+pub struct ControlsRow(Action);
+pub struct ControlsRowText(Action);
+pub struct AutoPickupText;
+pub enum ControlsButton {
+    Preset(Preset),
+    AutoPickup,
+    Back,
+}
+/// The action waiting for a key press to be bound to.
+#[derive(Default)]
+pub struct Rebinding(Option<Action>);
+
+pub fn controls_ui_create(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    controls: Res<Controls>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    rebinding.0 = None;
+
+    let font = asset_server.load("Roboto/Roboto-Regular.ttf");
+    let background = materials.add(Color::hex("101010").unwrap().into());
+    let text = |value: &str, font_size: f32, color: Color| {
+        Text::with_section(
+            value,
+            TextStyle {
+                font: font.clone(),
+                font_size,
+                color,
+            },
+            TextAlignment::default(),
+        )
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            material: background.clone(),
+            ..Default::default()
+        })
+        .insert(ControlsCanvas)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    margin: Rect::all(Val::Px(20.0)),
+                    ..Default::default()
+                },
+                text: text("CONTROLS", 60.0, Color::hex("826007").unwrap()),
+                ..Default::default()
+            });
+
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    margin: Rect::all(Val::Px(10.0)),
+                    ..Default::default()
+                },
+                text: text(
+                    "Click an action, then press its new key or mouse button",
+                    25.0,
+                    Color::hex("826007").unwrap(),
+                ),
+                ..Default::default()
+            });
+
+            // Two columns so every action fits on the screen
+            let actions: Vec<Action> = controls.bindings.keys().copied().collect();
+            let (left, right) = actions.split_at(actions.len().div_ceil(2));
+            parent
+                .spawn_bundle(NodeBundle {
+                    material: background.clone(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for column in [left, right] {
+                        parent
+                            .spawn_bundle(NodeBundle {
+                                style: Style {
+                                    flex_direction: FlexDirection::ColumnReverse,
+                                    margin: Rect::all(Val::Px(10.0)),
+                                    ..Default::default()
+                                },
+                                material: background.clone(),
+                                ..Default::default()
+                            })
+                            .with_children(|parent| {
+                                for &action in column {
+                                    parent
+                                        .spawn_bundle(ButtonBundle {
+                                            style: Style {
+                                                margin: Rect::all(Val::Px(2.0)),
+                                                ..Default::default()
+                                            },
+                                            material: background.clone(),
+                                            ..Default::default()
+                                        })
+                                        .insert(ControlsRow(action))
+                                        .with_children(|parent| {
+                                            parent
+                                                .spawn_bundle(TextBundle {
+                                                    text: text("", 25.0, Color::WHITE),
+                                                    ..Default::default()
+                                                })
+                                                .insert(ControlsRowText(action));
+                                        });
+                                }
+                            });
+                    }
+                });
+
+            parent
+                .spawn_bundle(ButtonBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(10.0)),
+                        ..Default::default()
+                    },
+                    material: background.clone(),
+                    ..Default::default()
+                })
+                .insert(ControlsButton::AutoPickup)
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(TextBundle {
+                            text: text("", 25.0, Color::WHITE),
+                            ..Default::default()
+                        })
+                        .insert(AutoPickupText);
+                });
+
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(20.0)),
+                        ..Default::default()
+                    },
+                    material: background.clone(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for preset in Preset::ALL {
+                        parent
+                            .spawn_bundle(ButtonBundle {
+                                style: Style {
+                                    margin: Rect::all(Val::Px(20.0)),
+                                    ..Default::default()
+                                },
+                                material: background.clone(),
+                                ..Default::default()
+                            })
+                            .insert(ControlsButton::Preset(preset))
+                            .with_children(|parent| {
+                                parent.spawn_bundle(TextBundle {
+                                    text: text(preset.name(), 40.0, Color::WHITE),
+                                    ..Default::default()
+                                });
+                            });
+                    }
+                });
+
+            parent
+                .spawn_bundle(ButtonBundle {
+                    material: background.clone(),
+                    ..Default::default()
+                })
+                .insert(ControlsButton::Back)
+                .with_children(|parent| {
+                    parent.spawn_bundle(TextBundle {
+                        text: text("BACK", 60.0, Color::WHITE),
+                        ..Default::default()
+                    });
+                });
+        });
+}
+
+pub fn controls_interaction(
+    rows: Query<(&Interaction, &ControlsRow), Changed<Interaction>>,
+    buttons: Query<(&Interaction, &ControlsButton), Changed<Interaction>>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    mut controls: ResMut<Controls>,
+    mut rebinding: ResMut<Rebinding>,
+    mut app_state: ResMut<State<AppState>>,
+) {
+    if let Some(action) = rebinding.0 {
+        // Shift on its own waits for the key pressed with it.
+        let pressed = keys
+            .get_just_pressed()
+            .find(|key| !matches!(key, KeyCode::LShift | KeyCode::RShift));
+        // The click on the action's row was in an earlier frame.
+        let binding = match (pressed, mouse.get_just_pressed().next()) {
+            (Some(KeyCode::Escape), _) => None,
+            (Some(&key), _) if is_shift_pressed(&keys) => Some(Binding::Shift(key)),
+            (Some(&key), _) => Some(Binding::Key(key)),
+            (None, Some(&button)) => Some(Binding::Mouse(button)),
+            (None, None) => return,
+        };
+        if let Some(binding) = binding {
+            controls.rebind(action, binding);
+            controls.store();
+        }
+        rebinding.0 = None;
+        return;
+    } else if keys.just_pressed(KeyCode::Escape) {
+        app_state.set(AppState::MainMenu).unwrap();
+        return;
+    }
+
+    for (interaction, row) in rows.iter() {
+        if *interaction == Interaction::Clicked {
+            rebinding.0 = Some(row.0);
+        }
+    }
+
+    for (interaction, button) in buttons.iter() {
+        match (interaction, button) {
+            (Interaction::Clicked, ControlsButton::Preset(preset)) => {
+                controls.bindings = preset.bindings();
+                controls.store();
+                rebinding.0 = None;
+            }
+            (Interaction::Clicked, ControlsButton::AutoPickup) => {
+                controls.auto_pickup = !controls.auto_pickup;
+                controls.store();
+            }
+            (Interaction::Clicked, ControlsButton::Back) => {
+                app_state.set(AppState::MainMenu).unwrap();
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn controls_ui_update(
+    controls: Res<Controls>,
+    rebinding: Res<Rebinding>,
+    mut texts: Query<(&mut Text, &ControlsRowText)>,
+    mut auto_pickup: Query<&mut Text, (With<AutoPickupText>, Without<ControlsRowText>)>,
+) {
+    if !controls.is_changed() && !rebinding.is_changed() {
+        return;
+    }
+
+    for (mut text, row) in texts.iter_mut() {
+        text.sections[0].value = if rebinding.0 == Some(row.0) {
+            format!("{}: press a key or button (Escape to cancel)", row.0)
+        } else {
+            format!("{}: {}", row.0, controls.describe(row.0))
+        };
+    }
+
+    if let Ok(mut text) = auto_pickup.single_mut() {
+        let state = if controls.auto_pickup { "on" } else { "off" };
+        text.sections[0].value = format!("Auto-explore picks up items: {}", state);
+    }
+}
+
+pub fn controls_cleanup(mut commands: Commands, q: Query<Entity, With<ControlsCanvas>>) {
+    commands.entity(q.single().unwrap()).despawn_recursive();
+}
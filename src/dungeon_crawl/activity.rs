@@ -0,0 +1,250 @@
+use super::{
+    log::{LogCategory, Logs},
+    Ev, GameData, Health, Item, Player, StatusEffects, StatusKind,
+};
+use crate::{
+    controls::Controls,
+    world_map::{GridPosition, TileFlags, WorldMap},
+};
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+/// Something the player keeps doing by themselves, a turn at a time, until it's done,
+/// something interrupts it or a key is pressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Activity {
+    /// Waiting until fully healed.
+    Rest,
+    /// Walking to the closest unexplored place.
+    Explore,
+    /// Walking to a known tile, stopping next to stairs instead of taking them.
+    Travel(GridPosition),
+}
+
+#[derive(Default)]
+pub struct PlayerActivity {
+    pub current: Option<Activity>,
+    /// Whether the activity hasn't taken a turn yet.
+    started: bool,
+    /// Length of the log on the previous turn of the activity.
+    log_len: usize,
+    /// Player's health on the previous turn of the activity.
+    health: i32,
+    /// Creatures in view on the previous turn of the activity.
+    in_view: Vec<Entity>,
+}
+
+impl PlayerActivity {
+    pub fn stop(&mut self) {
+        self.current = None;
+    }
+}
+
+type PlayerQuery<'a> = Query<
+    'a,
+    (
+        Entity,
+        &'static GridPosition,
+        &'static Health,
+        &'static StatusEffects,
+    ),
+    With<Player>,
+>;
+type CreatureQuery<'a> =
+    Query<'a, (Entity, &'static GridPosition), (With<Health>, Without<Player>)>;
+
+/// Everything the player's activities look at.
+#[derive(SystemParam)]
+pub struct Activities<'a> {
+    activity: ResMut<'a, PlayerActivity>,
+    logs: Res<'a, Logs>,
+    controls: Res<'a, Controls>,
+    player: PlayerQuery<'a>,
+    creatures: CreatureQuery<'a>,
+    loose_items: Query<'a, (Entity, &'static GridPosition), With<Item>>,
+}
+
+impl<'a> Activities<'a> {
+    pub fn current(&self) -> Option<Activity> {
+        self.activity.current
+    }
+
+    pub fn start(&mut self, activity: Activity) {
+        let health = self.player.single().map_or(0, |(_, _, h, _)| h.current);
+        *self.activity = PlayerActivity {
+            current: Some(activity),
+            started: true,
+            log_len: self.logs.0.len(),
+            health,
+            in_view: vec![],
+        };
+    }
+
+    pub fn stop(&mut self) {
+        self.activity.stop();
+    }
+
+    /// The player's action this turn, or the reason the activity stopped, if any.
+    pub fn step(&mut self, world: &WorldMap, data: &GameData) -> Result<Ev, Option<&'static str>> {
+        let activity = match self.activity.current {
+            Some(activity) => activity,
+            None => return Err(None),
+        };
+        let health = *self.player.single().unwrap().2;
+        let hurt = health.current < self.activity.health;
+        let in_view: Vec<Entity> = self
+            .creatures
+            .iter()
+            .filter(|(_, pos)| world.tiles[**pos].contains(TileFlags::IN_VIEW))
+            .map(|(creature, _)| creature)
+            .collect();
+        let enemy_in_view = !in_view.is_empty();
+        let started = self.activity.started;
+        // Travelling goes on past the creatures that were already there.
+        let enemy_appeared = !started && in_view.iter().any(|e| !self.activity.in_view.contains(e));
+        // Newest first. Damage is logged as combat, also from statuses, while status
+        // messages like healing from regeneration are no reason to stop.
+        let new_messages = self.logs.0.len() - self.activity.log_len;
+        let disturbed = self
+            .logs
+            .0
+            .iter()
+            .take(new_messages)
+            .any(|entry| entry.category == LogCategory::Combat);
+
+        let step = match activity {
+            Activity::Rest if enemy_in_view && started => {
+                Err(Some("You can't rest with enemies in view."))
+            }
+            Activity::Rest if enemy_in_view || hurt || disturbed => Err(Some("You stop resting.")),
+            Activity::Rest if health.current >= health.max => Err(Some("You feel rested.")),
+            Activity::Rest => Ok(Ev::Nothing),
+            Activity::Explore if enemy_in_view && started => {
+                Err(Some("There are enemies in view."))
+            }
+            Activity::Explore if enemy_in_view || hurt => Err(Some("You stop exploring.")),
+            Activity::Explore => self.explore(world, data),
+            Activity::Travel(_) if enemy_appeared || hurt || disturbed => {
+                Err(Some("You stop travelling."))
+            }
+            Activity::Travel(target) => self.travel(world, target),
+        };
+
+        self.activity.started = false;
+        self.activity.health = health.current;
+        self.activity.log_len = self.logs.0.len();
+        self.activity.in_view = in_view;
+        if step.is_err() {
+            self.activity.stop();
+        }
+        step
+    }
+
+    fn explore(&mut self, world: &WorldMap, data: &GameData) -> Result<Ev, Option<&'static str>> {
+        let (player, &position, _, statuses) = self.player.single().unwrap();
+        if statuses.has(StatusKind::Blindness) {
+            return Err(Some("You can't explore while blind."));
+        }
+
+        let pick_up = self.controls.auto_pickup && data.inventory.iter().any(Option::is_none);
+        let items: Vec<(Entity, GridPosition)> = self
+            .loose_items
+            .iter()
+            .map(|(item, pos)| (item, *pos))
+            .filter(|(_, pos)| world.tiles[*pos].contains(TileFlags::EXPLORED))
+            .collect();
+        if pick_up {
+            if let Some(&(item, _)) = items.iter().find(|(_, pos)| *pos == position) {
+                return Ok(Ev::PickUpItem(player, item));
+            }
+        }
+
+        // Tiles next to unexplored ones, and items if they're picked up
+        let goal = |pos: GridPosition| {
+            pos != position
+                && passable(world, pos)
+                && (world
+                    .neighbours(pos)
+                    .iter()
+                    .any(|n| !world.tiles[*n].contains(TileFlags::EXPLORED))
+                    || pick_up && items.iter().any(|(_, item)| *item == pos))
+        };
+        if let Some(path) = world.path_to_nearest(position, goal, |pos| passable(world, pos)) {
+            return walk(world, player, position, path[1]);
+        }
+
+        match known_stairs(world) {
+            Some(stairs) if self.activity.started => {
+                self.activity.current = Some(Activity::Travel(stairs));
+                self.travel(world, stairs)
+            }
+            Some(_) => Err(Some(
+                "There's nothing left to explore. Explore again to travel to the stairs.",
+            )),
+            None => Err(Some("There's nothing left to explore.")),
+        }
+    }
+
+    fn travel(&self, world: &WorldMap, target: GridPosition) -> Result<Ev, Option<&'static str>> {
+        let (player, &position, _, _) = self.player.single().unwrap();
+        if position == target || !can_travel_to(world, target) {
+            return Err(None);
+        }
+
+        match travel_path(world, position, target) {
+            None => Err(Some("You don't know a way there.")),
+            // Taking the stairs is up to the player.
+            Some(path) if Some(path[1]) == world.stairs || Some(path[1]) == world.up_stairs => {
+                Err(Some("You reach the stairs."))
+            }
+            Some(path) => walk(world, player, position, path[1]),
+        }
+    }
+}
+
+/// The stairs down, if the player has seen them.
+pub fn known_stairs(world: &WorldMap) -> Option<GridPosition> {
+    world
+        .stairs
+        .filter(|stairs| world.tiles[*stairs].contains(TileFlags::EXPLORED))
+}
+
+/// Known places to travel to, the stairs included.
+fn can_travel_to(world: &WorldMap, target: GridPosition) -> bool {
+    world.tiles.get(target.x, target.y).is_some_and(|tile| {
+        tile.contains(TileFlags::EXPLORED) && !tile.contains(TileFlags::BLOCKS_PATHFINDING)
+    })
+}
+
+/// The way travel takes from `start` to `target` over explored tiles, starting with `start`.
+pub fn travel_path(
+    world: &WorldMap,
+    start: GridPosition,
+    target: GridPosition,
+) -> Option<Vec<GridPosition>> {
+    if !can_travel_to(world, target) {
+        return None;
+    }
+    world.path_to_nearest(start, |pos| pos == target, |pos| passable(world, pos))
+}
+
+/// Explored floor the player can walk over without taking the stairs.
+fn passable(world: &WorldMap, pos: GridPosition) -> bool {
+    let tile = world.tiles[pos];
+    tile.contains(TileFlags::EXPLORED)
+        && !tile.contains(TileFlags::BLOCKS_PATHFINDING)
+        && Some(pos) != world.stairs
+        && Some(pos) != world.up_stairs
+}
+
+fn walk(
+    world: &WorldMap,
+    player: Entity,
+    position: GridPosition,
+    next: GridPosition,
+) -> Result<Ev, Option<&'static str>> {
+    if world.tiles[next].contains(TileFlags::BLOCKS_MOVEMENT) {
+        Err(Some("Something is in the way."))
+    } else {
+        Ok(Ev::Move(player, position, next))
+    }
+}
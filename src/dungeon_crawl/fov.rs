@@ -1,54 +1,20 @@
-use super::{Cursor, Player};
-use crate::world_map::{GridPosition, Tile, TileFlags, WorldMap};
+use super::{Cursor, Player, StatusEffects, ViewRadius};
+use crate::world_map::{Array2D, GridPosition, Tile, TileFlags, WorldMap};
 use bevy::prelude::*;
 
 pub fn player_fov(
-    player: Query<&GridPosition, With<Player>>,
+    player: Query<(&GridPosition, &ViewRadius, &StatusEffects), With<Player>>,
     mut visible: Query<(&mut Visible, &GridPosition, Option<&Tile>), Without<Cursor>>,
     mut tiles: Query<(&mut Handle<ColorMaterial>, &GridPosition), With<Tile>>,
     mut world: ResMut<WorldMap>,
 ) {
-    let position = match player.single() {
-        Ok(position) => position.clone(),
+    let (&position, radius, statuses) = match player.single() {
+        Ok(player) => player,
         Err(_) => return,
     };
 
-    for end in fov_circle(position.x, position.y, 4) {
-        let mut previous = None;
-        for (x, y) in line_drawing::Bresenham::new((position.x, position.y), end) {
-            if let Some(&tile) = world.tiles.get(x, y) {
-                // Don't go through diagonal walls.
-                if let Some((prev_x, prev_y)) = previous {
-                    if (world.tiles[[prev_x, y]] & world.tiles[[x, prev_y]])
-                        .contains(TileFlags::BLOCKS_VISION)
-                    {
-                        break;
-                    }
-                }
-                previous = Some((x, y));
-
-                world.tiles[[x, y]] |= TileFlags::IN_VIEW;
-
-                // Remove artifacts
-                if !tile.contains(TileFlags::BLOCKS_VISION) {
-                    // Different direction depending in which quadrant we are in.
-                    for (i, j) in [
-                        ((x - position.x).signum(), 0),
-                        (0, (y - position.y).signum()),
-                    ] {
-                        if let Some(neigh) = world.tiles.get_mut(x + i, y + j) {
-                            if neigh.contains(TileFlags::BLOCKS_VISION) {
-                                *neigh |= TileFlags::IN_VIEW;
-                            }
-                        }
-                    }
-                }
-
-                if tile.contains(TileFlags::BLOCKS_VISION) {
-                    break;
-                }
-            }
-        }
+    for pos in field_of_view(&world.tiles, position, statuses.view_radius(radius.0)) {
+        world.tiles[pos] |= TileFlags::IN_VIEW;
     }
 
     for x in 0..world.entities.size().x {
@@ -88,27 +54,149 @@ pub fn player_fov(
     }
 }
 
-fn fov_circle(x: i32, y: i32, r: i32) -> Vec<(i32, i32)> {
-    let mut points = vec![];
-    for off in 0..=r {
-        points.push((x + off, y + r));
-        points.push((x - off, y + r));
-        points.push((x + off, y - r));
-        points.push((x - off, y - r));
-        points.push((x + r, y + off));
-        points.push((x - r, y + off));
-        points.push((x + r, y - off));
-        points.push((x - r, y - off));
+/// Symmetric shadowcasting: if a tile is visible from another one, it also works
+/// the other way around. Tiles outside of the map block vision.
+/// Returns all tiles visible from `origin` within `radius`, including walls.
+pub fn field_of_view(
+    tiles: &Array2D<TileFlags>,
+    origin: GridPosition,
+    radius: i32,
+) -> Vec<GridPosition> {
+    let mut visible = vec![origin];
+    if radius <= 0 {
+        return visible;
     }
-    for off in 0..=(r / 2) {
-        points.push((x + off, y + r + 1));
-        points.push((x - off, y + r + 1));
-        points.push((x + off, y - r - 1));
-        points.push((x - off, y - r - 1));
-        points.push((x + r + 1, y + off));
-        points.push((x - r - 1, y + off));
-        points.push((x + r + 1, y - off));
-        points.push((x - r - 1, y - off));
+
+    for quadrant in 0..4 {
+        // Transform (depth, column) in the quadrant into map coordinates.
+        let transform = |depth: i32, col: i32| match quadrant {
+            0 => GridPosition {
+                x: origin.x + col,
+                y: origin.y + depth,
+            },
+            1 => GridPosition {
+                x: origin.x + depth,
+                y: origin.y + col,
+            },
+            2 => GridPosition {
+                x: origin.x + col,
+                y: origin.y - depth,
+            },
+            _ => GridPosition {
+                x: origin.x - depth,
+                y: origin.y + col,
+            },
+        };
+        let is_wall = |pos: GridPosition| {
+            tiles
+                .get(pos.x, pos.y)
+                .is_none_or(|t| t.contains(TileFlags::BLOCKS_VISION))
+        };
+
+        // Rows still to scan: depth, start slope and end slope. Slopes are
+        // fractions (numerator, denominator) with a positive denominator.
+        let mut rows = vec![(1, (-1, 1), (1, 1))];
+        while let Some((depth, mut start, end)) = rows.pop() {
+            if depth > radius {
+                continue;
+            }
+
+            // Columns from round_ties_up(depth * start) to round_ties_down(depth * end)
+            let min_col = (2 * depth * start.0 + start.1).div_euclid(2 * start.1);
+            let max_col = -(end.1 - 2 * depth * end.0).div_euclid(2 * end.1);
+
+            let mut previous_wall = None;
+            for col in min_col..=max_col {
+                let pos = transform(depth, col);
+                let wall = is_wall(pos);
+                let symmetric = col * start.1 >= depth * start.0 && col * end.1 <= depth * end.0;
+
+                if (wall || symmetric) && col * col + depth * depth <= radius * radius + radius {
+                    visible.push(pos);
+                }
+
+                let slope = (2 * col - 1, 2 * depth);
+                if previous_wall == Some(true) && !wall {
+                    start = slope;
+                }
+                if previous_wall == Some(false) && wall {
+                    rows.push((depth + 1, start, slope));
+                }
+                previous_wall = Some(wall);
+            }
+
+            if previous_wall == Some(false) {
+                rows.push((depth + 1, start, end));
+            }
+        }
+    }
+
+    visible
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(x: i32, y: i32) -> GridPosition {
+        GridPosition { x, y }
+    }
+
+    fn open_room() -> Array2D<TileFlags> {
+        Array2D::with_size(15, 15)
+    }
+
+    #[test]
+    fn sees_an_open_room_within_the_radius() {
+        let visible = field_of_view(&open_room(), pos(7, 7), 3);
+        assert!(visible.contains(&pos(7, 10)));
+        assert!(visible.contains(&pos(4, 7)));
+        assert!(visible.contains(&pos(9, 9)));
+        assert!(!visible.contains(&pos(7, 11)));
+        assert!(!visible.contains(&pos(10, 10)));
+    }
+
+    #[test]
+    fn walls_are_seen_but_hide_what_is_behind() {
+        let mut tiles = open_room();
+        tiles[[7, 9]] = TileFlags::BLOCKS_VISION;
+        let visible = field_of_view(&tiles, pos(7, 7), 5);
+        assert!(visible.contains(&pos(7, 9)));
+        assert!(!visible.contains(&pos(7, 10)));
+        assert!(!visible.contains(&pos(7, 11)));
+    }
+
+    #[test]
+    fn sight_is_symmetric() {
+        let mut tiles = open_room();
+        for (x, y) in [(5, 5), (6, 9), (9, 6), (8, 8), (3, 10), (10, 4)] {
+            tiles[[x, y]] = TileFlags::BLOCKS_VISION;
+        }
+        for x in 1..14 {
+            for y in 1..14 {
+                let from = pos(x, y);
+                if tiles[from].contains(TileFlags::BLOCKS_VISION) {
+                    continue;
+                }
+                for to in field_of_view(&tiles, from, 8) {
+                    let wall = tiles
+                        .get(to.x, to.y)
+                        .is_none_or(|t| t.contains(TileFlags::BLOCKS_VISION));
+                    if !wall {
+                        assert!(
+                            field_of_view(&tiles, to, 8).contains(&from),
+                            "{:?} sees {:?} but not the other way around",
+                            from,
+                            to
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn no_radius_sees_only_the_origin() {
+        assert_eq!(field_of_view(&open_room(), pos(7, 7), 0), vec![pos(7, 7)]);
     }
-    points
 }
@@ -0,0 +1,48 @@
+use super::GameData;
+use bevy::{
+    app::{Events, ManualEventReader},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogCategory {
+    Combat,
+    Loot,
+    Status,
+    System,
+}
+
+pub struct LogMessage(pub LogCategory, pub String);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub turn: u32,
+    pub category: LogCategory,
+    pub text: String,
+}
+
+/// Every message of the run, newest first.
+#[derive(Default)]
+pub struct Logs(pub VecDeque<LogEntry>);
+
+/// Where `collect_log` is in the messages. It runs in several states and each message
+/// must only be kept once.
+#[derive(Default)]
+pub struct LogReader(ManualEventReader<LogMessage>);
+
+pub fn collect_log(
+    messages: Res<Events<LogMessage>>,
+    mut reader: ResMut<LogReader>,
+    mut log: ResMut<Logs>,
+    data: Res<GameData>,
+) {
+    for LogMessage(category, text) in reader.0.iter(&messages) {
+        log.0.push_front(LogEntry {
+            turn: data.turn,
+            category: *category,
+            text: text.clone(),
+        });
+    }
+}
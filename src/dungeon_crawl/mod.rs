@@ -1,16 +1,44 @@
+mod activity;
 mod fov;
+mod log;
+#[cfg(not(feature = "headless"))]
+mod morgue;
+pub mod save;
 mod setup;
+mod status;
+mod targeting;
+#[cfg(not(feature = "headless"))]
 mod ui;
 
-use self::ui::{Logs, MyCanvas};
+pub use log::{LogCategory, LogMessage, Logs};
+pub use status::{Status, StatusEffects, StatusKind};
+#[cfg(not(feature = "headless"))]
+pub use ui::DungeonCrawlUIPlugin;
+
+use self::{
+    activity::{known_stairs, Activities, Activity, PlayerActivity},
+    log::LogReader,
+};
+#[cfg(not(feature = "headless"))]
+use crate::high_scores;
 use crate::{
-    dungeon_crawl::ui::LogMessage,
-    world_map::{GridPosition, TileFlags, WorldMap},
+    controls::{Action, ActionInput},
+    items::{Effect, ItemTable, ItemTemplates, Targeting},
+    monsters::{AIType, MonsterTable},
+    world_generation::MapGeneratorKind,
+    world_map::{DijkstraMap, GridPosition, TileFlags, WorldMap},
     AppState,
 };
-use bevy::{ecs::system::QuerySingleError, prelude::*};
-use rand::random;
-use std::collections::{HashMap, VecDeque};
+use bevy::{
+    ecs::system::{QuerySingleError, SystemParam},
+    prelude::*,
+};
+use rand::{random, rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TurnState {
@@ -18,14 +46,27 @@ pub enum TurnState {
     Turn,
 }
 
+/// What dealt damage, to know who gets the kill.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DamageSource {
+    /// An attack or item of the creature, which may be the damaged one itself.
+    Creature(Entity),
+    Status(StatusKind),
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Ev {
     Move(Entity, GridPosition, GridPosition),
-    Attack(Entity, Entity, i32),
+    /// Melee attack that has to roll to hit.
+    Attack(Entity, Entity),
+    Damage(DamageSource, Entity, i32),
     PickUpItem(Entity, Entity),
     DropItem(Entity, Entity, GridPosition),
+    UseItem(Entity, Entity, GridPosition),
     Heal(Entity, i32),
-    Paralyze(Entity, i32),
+    AddStatus(Entity, Status),
+    TickStatus(Entity, Status),
+    RemoveStatus(Entity, StatusKind),
     RemoveFromMap(Entity),
     AddToMap(Entity, GridPosition),
     RemoveFromInitiative(Entity),
@@ -33,18 +74,88 @@ pub enum Ev {
     Nothing,
     Quit,
     Descend,
+    Ascend,
 }
 
+impl Ev {
+    /// Energy the acting entity spends on this event, if it's an action.
+    fn cost(&self) -> Option<i32> {
+        match self {
+            Ev::Move(..) | Ev::Attack(..) | Ev::UseItem(..) | Ev::Nothing => {
+                Some(Scheduler::ACTION_COST)
+            }
+            Ev::PickUpItem(..) | Ev::DropItem(..) => Some(Scheduler::ACTION_COST / 2),
+            _ => None,
+        }
+    }
+}
+
+/// Energy based turn order. Every tick each actor gains energy equal to its
+/// speed and the one with the most energy acts once it has `ACTION_COST`.
 #[derive(Default, Clone)]
-pub struct InitiativeOrder(pub VecDeque<Entity>);
+pub struct Scheduler {
+    /// Actors in the order they joined, so ties are broken consistently.
+    pub actors: Vec<(Entity, i32)>,
+}
+
+impl Scheduler {
+    pub const ACTION_COST: i32 = 100;
+
+    pub fn add(&mut self, entity: Entity) {
+        if !self.actors.iter().any(|(e, _)| *e == entity) {
+            self.actors.push((entity, 0));
+        }
+    }
+
+    pub fn remove(&mut self, entity: Entity) {
+        self.actors.retain(|(e, _)| *e != entity);
+    }
+
+    pub fn spend(&mut self, entity: Entity, cost: i32) {
+        if let Some((_, energy)) = self.actors.iter_mut().find(|(e, _)| *e == entity) {
+            *energy -= cost;
+        }
+    }
+
+    /// Advances time until someone has enough energy to act and returns them.
+    pub fn next(&mut self, speed: impl Fn(Entity) -> i32) -> Option<Entity> {
+        if self.actors.is_empty() {
+            return None;
+        }
+        loop {
+            let ready = self
+                .actors
+                .iter()
+                .filter(|(_, energy)| *energy >= Self::ACTION_COST)
+                // max_by_key returns the last maximum, so reverse to get the first one
+                .rev()
+                .max_by_key(|(_, energy)| *energy);
+            if let Some(&(entity, _)) = ready {
+                return Some(entity);
+            }
+
+            for (entity, energy) in &mut self.actors {
+                *energy += i32::max(speed(*entity), 1);
+            }
+        }
+    }
+}
 
 pub struct DungeonCrawlPlugin;
 impl Plugin for DungeonCrawlPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_event::<Ev>()
-            .add_plugin(ui::DungeonCrawlUIPlugin)
+            .add_event::<LogMessage>()
+            .init_resource::<Logs>()
+            .init_resource::<LogReader>()
             .init_resource::<GameData>()
-            .init_resource::<InitiativeOrder>();
+            .init_resource::<GameRng>()
+            .init_resource::<PlayerActivity>()
+            .init_resource::<PlayerMaps>()
+            .init_resource::<MonsterTable>()
+            .init_resource::<ItemTable>()
+            .init_resource::<Scheduler>()
+            .init_resource::<save::Dungeon>();
 
         macro_rules! switch_app_state {
             ($e:expr) => {
@@ -61,14 +172,32 @@ impl Plugin for DungeonCrawlPlugin {
         );
         app.add_system_set(
             SystemSet::on_enter(AppState::DungeonCrawlExitToMenu)
-                .with_system(cleanup.system())
+                .with_system(cleanup.system().label("cleanup"))
                 .with_system(cleanup_log_and_inventory.system())
                 .with_system(switch_app_state!(AppState::MainMenu).system()),
         );
+        app.add_system_set(
+            SystemSet::on_enter(AppState::EndOfRun)
+                .with_system(cleanup.system().label("cleanup"))
+                .with_system(cleanup_log_and_inventory.system()),
+        );
+        app.add_system_set(
+            SystemSet::on_enter(AppState::LoadGame).with_system(save::load_game.system()),
+        );
+        app.add_system_set(
+            SystemSet::on_enter(AppState::LoadFloor).with_system(save::load_floor.system()),
+        );
         app.add_system_set(
             SystemSet::on_enter(AppState::DungeonCrawlDescend)
-                .with_system(cleanup.system())
-                .with_system(switch_app_state!(AppState::WorldGeneration).system()),
+                .with_system(save::store_floor.system().before("cleanup"))
+                .with_system(cleanup.system().label("cleanup"))
+                .with_system(save::change_floor.system().after("cleanup")),
+        );
+        app.add_system_set(
+            SystemSet::on_enter(AppState::DungeonCrawlAscend)
+                .with_system(save::store_floor.system().before("cleanup"))
+                .with_system(cleanup.system().label("cleanup"))
+                .with_system(save::change_floor.system().after("cleanup")),
         );
 
         use fov::*;
@@ -78,6 +207,7 @@ impl Plugin for DungeonCrawlPlugin {
                 .with_system(update_world_map.system().label("update_world_map"))
                 .with_system(handle_initiative.system())
                 .with_system(player_fov.system().after("update_world_map"))
+                .with_system(update_player_maps.system().after("update_world_map"))
                 .with_system(switch_app_state!(AppState::DungeonCrawl(TurnState::Turn)).system()),
         );
 
@@ -86,7 +216,15 @@ impl Plugin for DungeonCrawlPlugin {
                 .before("evs")
                 .with_system(player_control.system())
                 .with_system(enemy_ai.system())
-                .with_system(paralyzed.system()),
+                .with_system(status::forced_action.system()),
+        );
+
+        app.add_system_set(
+            SystemSet::on_enter(AppState::Targeting).with_system(targeting::snap_cursor.system()),
+        );
+        app.add_system_set(
+            SystemSet::on_update(AppState::Targeting)
+                .with_system(targeting::targeting_control.system().label("targeting")),
         );
 
         app.add_system_set(
@@ -94,6 +232,51 @@ impl Plugin for DungeonCrawlPlugin {
                 .label("evs")
                 .with_system(handle_evs.system()),
         );
+
+        // Runs in the same frame as the events so the last messages of a run are kept.
+        app.add_system_set(
+            SystemSet::on_update(AppState::DungeonCrawl(TurnState::Turn))
+                .after("evs")
+                .with_system(log::collect_log.system()),
+        );
+        // Screens opened over the dungeon log too, like why an item can't be used there.
+        app.add_system_set(
+            SystemSet::on_update(AppState::Targeting)
+                .with_system(log::collect_log.system().after("targeting")),
+        );
+        for state in [AppState::Look, AppState::MessageHistory] {
+            app.add_system_set(SystemSet::on_update(state).with_system(log::collect_log.system()));
+        }
+    }
+}
+
+/// What a run leaves outside of the game: the saved game, the morgue file and the
+/// high scores. The headless app goes without it, so simulations keep the player's files.
+#[cfg(not(feature = "headless"))]
+pub struct PersistencePlugin;
+#[cfg(not(feature = "headless"))]
+impl Plugin for PersistencePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_enter(AppState::DungeonCrawlExitToMenu)
+                .with_system(save::save_game.system().before("cleanup")),
+        );
+        app.add_system_set(
+            SystemSet::on_enter(AppState::EndOfRun)
+                .with_system(save::delete_save.system())
+                .with_system(morgue::write_morgue.system().before("cleanup"))
+                .with_system(high_scores::record_score.system().before("cleanup")),
+        );
+        for state in [
+            AppState::DungeonCrawl(TurnState::Turn),
+            AppState::MessageHistory,
+            AppState::Look,
+            AppState::Targeting,
+        ] {
+            app.add_system_set(
+                SystemSet::on_update(state).with_system(save::save_on_exit.system()),
+            );
+        }
     }
 }
 
@@ -104,33 +287,76 @@ pub struct GameData {
     pub weapon: Option<Entity>,
 
     pub previous_hp: Option<Health>,
+    /// Statuses the player had when leaving the last floor, they go on after the stairs.
+    pub previous_statuses: StatusEffects,
+    pub seed: u64,
+    /// Number of player actions so far.
+    pub turn: u32,
+    /// The current floor, other visited floors are kept in `save::Dungeon`.
     pub floor: u32,
 
     pub level: u32,
     pub current_xp: u32,
     pub needed_xp: u32,
+    /// Number of kills by monster name.
+    pub kills: BTreeMap<String, u32>,
+    /// Set once the run is over, shown on the end-of-run screen.
+    pub outcome: Option<RunOutcome>,
+}
+
+pub enum RunOutcome {
+    Won {
+        boss: String,
+    },
+    /// `killer` is `None` when the player killed themselves, e.g. with a thrown item.
+    Died {
+        killer: Option<String>,
+    },
+    /// Died from a status effect like poison.
+    Succumbed {
+        status: StatusKind,
+    },
+}
+
+impl fmt::Display for RunOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunOutcome::Won { boss } => write!(f, "Slew the {}", boss),
+            RunOutcome::Died {
+                killer: Some(killer),
+            } => write!(f, "Killed by {}", killer),
+            RunOutcome::Died { killer: None } => write!(f, "Died by their own hand"),
+            RunOutcome::Succumbed { status } => write!(f, "Died while {}", status.adjective()),
+        }
+    }
 }
 
 impl GameData {
+    /// The boss waits on the final floor and there are no stairs further down.
+    pub const FINAL_FLOOR: u32 = 5;
+    /// The player heals 1 health every this many turns.
+    pub const REGEN_TURNS: u32 = 10;
+
     const MAP_SIZE: [(u32, (u32, u32)); 2] = [(1, (200, 400)), (4, (400, 600))];
+    const MAP_GENERATOR: [(u32, MapGeneratorKind); 5] = [
+        (1, MapGeneratorKind::CellularAutomata),
+        (2, MapGeneratorKind::Bsp),
+        (3, MapGeneratorKind::DrunkardsWalk),
+        (4, MapGeneratorKind::Maze),
+        (5, MapGeneratorKind::CellularAutomata),
+    ];
 
     const ENEMY_COUNT: [(u32, u32); 3] = [(1, 3), (2, 4), (4, 6)];
     const ITEM_COUNT: [(u32, u32); 3] = [(1, 2), (2, 3), (4, 4)];
 
-    const ITEM_CHANCES: [(u32, (Item, i32)); 7] = [
-        (1, (Item::HealthPotion, 10)),
-        (2, (Item::Sword, 5)),
-        (2, (Item::ScrollOfLightning, 5)),
-        (3, (Item::Armor, 5)),
-        (4, (Item::ScrollOfFireball, 5)),
-        (4, (Item::ScrollOfParalysis, 5)),
-        (4, (Item::WarAxe, 5)),
-    ];
-
     pub fn floor_map_size(&self) -> (u32, u32) {
         self.calculate_count(Self::MAP_SIZE)
     }
 
+    pub fn floor_map_generator(&self) -> MapGeneratorKind {
+        self.calculate_count(Self::MAP_GENERATOR)
+    }
+
     pub fn floor_enemy_count(&self) -> u32 {
         self.calculate_count(Self::ENEMY_COUNT)
     }
@@ -139,29 +365,17 @@ impl GameData {
         self.calculate_count(Self::ITEM_COUNT)
     }
 
-    pub fn floor_item(&self) -> Item {
-        let mut map = HashMap::new();
-        for (floor, (item, chance)) in Self::ITEM_CHANCES {
-            if floor > self.floor {
-                break;
-            }
-            map.insert(item, chance);
-        }
-
-        let sum: i32 = map.values().sum();
-        let mut rand = 1 + random::<i32>() % sum;
-
-        for (item, chance) in map {
-            rand -= chance;
-            if rand <= 0 {
-                return item;
-            }
-        }
-        unreachable!()
+    /// Rng for generating the current floor. The same seed and floor always give the same rng.
+    pub fn floor_rng(&self) -> GameRng {
+        let floor = (self.floor as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        GameRng(StdRng::seed_from_u64(self.seed ^ floor))
     }
 
+    /// The value of the last tier that starts on or before the current floor.
+    /// Tables are sorted by the floor their tier starts on.
     fn calculate_count<T: Copy, const N: usize>(&self, arr: [(u32, T); N]) -> T {
         arr.iter()
+            .rev()
             .find(|(floor, _)| *floor < self.floor + 1)
             .unwrap()
             .1
@@ -177,30 +391,57 @@ impl Default for GameData {
             weapon: None,
 
             previous_hp: None,
+            previous_statuses: StatusEffects::default(),
+            seed: random(),
+            turn: 0,
             floor: 1,
 
             level: 1,
             current_xp: 0,
             needed_xp: 3,
+            kills: BTreeMap::new(),
+            outcome: None,
         }
     }
 }
 
+pub struct GameRng(pub StdRng);
+
+impl Default for GameRng {
+    fn default() -> Self {
+        Self(StdRng::from_entropy())
+    }
+}
+
+impl GameRng {
+    /// Picks one of the choices with probability proportional to its weight.
+    pub fn weighted<'a, T>(&mut self, choices: &[(&'a T, i32)]) -> &'a T {
+        let sum: i32 = choices.iter().map(|(_, weight)| weight).sum();
+        let mut rand = self.0.gen_range(1..=sum);
+
+        for (choice, weight) in choices {
+            rand -= weight;
+            if rand <= 0 {
+                return choice;
+            }
+        }
+        unreachable!()
+    }
+}
+
 pub struct Player;
-pub struct EnemyAI;
+/// Killing it wins the game.
+pub struct Boss;
+pub struct EnemyAI {
+    pub ai: AIType,
+    /// Health at which the monster runs from the player.
+    pub flees_at: i32,
+}
 pub struct Initiative;
 pub struct Name(pub String);
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
-pub enum Item {
-    HealthPotion,
-    ScrollOfLightning,
-    ScrollOfParalysis,
-    ScrollOfFireball,
-    Sword,
-    WarAxe,
-    Armor,
-}
-pub struct Paralyzed(i32);
+/// Name of the item's template in the `ItemTable`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Item(pub String);
 pub struct Cursor;
 
 impl Name {
@@ -211,7 +452,7 @@ impl Name {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct Health {
     pub current: i32,
     pub max: i32,
@@ -223,156 +464,184 @@ impl Health {
     }
 }
 
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct CombatStats {
+    /// Damage dealt by melee attacks.
+    pub power: i32,
+    /// Subtracted from damage taken from melee attacks.
+    pub defense: i32,
+    pub accuracy: i32,
+    pub evasion: i32,
+}
+
+impl CombatStats {
+    /// Natural rolls at or below this always miss.
+    const FUMBLE: i32 = 1;
+    /// Natural rolls at or above this always hit and deal double damage.
+    const CRITICAL: i32 = 20;
+    /// Roll plus accuracy minus evasion needed to hit.
+    const TO_HIT: i32 = 6;
+}
+
+impl std::ops::Add for CombatStats {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            power: self.power + other.power,
+            defense: self.defense + other.defense,
+            accuracy: self.accuracy + other.accuracy,
+            evasion: self.evasion + other.evasion,
+        }
+    }
+}
+
+/// How quickly an entity gains energy in the `Scheduler`.
+#[derive(Clone, Copy)]
+pub struct Speed(pub i32);
+
+impl Speed {
+    pub const NORMAL: i32 = 100;
+}
+
+/// How far an entity can see.
+#[derive(Clone, Copy)]
+pub struct ViewRadius(pub i32);
+
+/// What a monster knows about the player.
+#[derive(Clone, Copy)]
+pub enum Awareness {
+    Unaware,
+    /// The monster goes to where it last saw the player and
+    /// gives up after `memory` turns without seeing them.
+    Alerted {
+        last_seen: GridPosition,
+        memory: i32,
+    },
+}
+
+impl Awareness {
+    const MEMORY: i32 = 10;
+}
+
+/// Ways to and away from the player for every monster. They only depend on the terrain,
+/// so they're rebuilt when the player moves or the floor changes.
+#[derive(Default)]
+pub struct PlayerMaps {
+    pub chase: DijkstraMap,
+    pub flee: DijkstraMap,
+    /// Where the player was when `chase` and `flee` were built.
+    pub built_from: Option<GridPosition>,
+    /// Ways to the places where monsters last saw the player.
+    pub last_seen: HashMap<GridPosition, DijkstraMap>,
+}
+
+impl PlayerMaps {
+    /// The way to where the player was last seen, found the first time it's needed.
+    pub fn way_to(&mut self, world: &WorldMap, last_seen: GridPosition) -> &DijkstraMap {
+        self.last_seen
+            .entry(last_seen)
+            .or_insert_with(|| DijkstraMap::new(world, &[last_seen]))
+    }
+}
+
 fn player_control(
-    mut player_q: Query<
-        (Entity, &GridPosition),
-        (With<Initiative>, Without<Paralyzed>, With<Player>),
-    >,
+    mut player_q: Query<(Entity, &GridPosition, &StatusEffects), (With<Initiative>, With<Player>)>,
     healthy_entities: Query<(), With<Health>>,
     mut inventory: ResMut<GameData>,
     world: Res<WorldMap>,
-    keys: Res<Input<KeyCode>>,
-    buttons: Res<Input<MouseButton>>,
+    input: ActionInput,
     items: Query<(Entity, Option<&GridPosition>, &Item)>,
+    mut app_state: ResMut<State<AppState>>,
+    mut activities: Activities,
     cursor: Query<&GridPosition, With<Cursor>>,
-    controllers: Query<Entity, Or<(With<Player>, With<EnemyAI>)>>,
     mut evs: EventWriter<Ev>,
+    mut log: EventWriter<LogMessage>,
 ) {
+    let pressed = input.just_pressed_action();
+    // Any key stops what the player was doing by themselves, cancel does only that then.
+    if pressed.is_some() && activities.current().is_some() {
+        activities.stop();
+        if pressed == Some(Action::Cancel) {
+            return;
+        }
+    }
+
     let (player_entity, position) = match player_q.single_mut() {
-        Ok((e, pos)) => (e, pos),
+        Ok((_, _, statuses)) if statuses.skips_turn() => return,
+        Ok((e, pos, _)) => (e, pos),
         Err(QuerySingleError::NoEntities(_)) => return,
         Err(QuerySingleError::MultipleEntities(_)) => panic!(),
     };
     let mut new_pos = position.clone();
 
-    if keys.is_changed() {
-        match keys.get_just_pressed().next() {
-            Some(KeyCode::Up | KeyCode::W) => new_pos.y += 1,
-            Some(KeyCode::Down | KeyCode::S) => new_pos.y -= 1,
-            Some(KeyCode::Left | KeyCode::A) => new_pos.x -= 1,
-            Some(KeyCode::Right | KeyCode::D) => new_pos.x += 1,
-            Some(KeyCode::G) => {
-                inventory.selected = None;
-                if let Some((item, _, _)) =
-                    items.iter().find(|(_, item, _)| item.contains(&position))
-                {
-                    evs.send(Ev::PickUpItem(player_entity, item));
-                }
-                return;
+    match pressed {
+        Some(Action::PickUp) => {
+            inventory.selected = None;
+            if let Some((item, _, _)) = items.iter().find(|(_, item, _)| item.contains(&position)) {
+                evs.send(Ev::PickUpItem(player_entity, item));
+            }
+            return;
+        }
+        Some(Action::UseSlot(slot)) if slot < inventory.inventory.len() => {
+            inventory.selected = Some(slot)
+        }
+        Some(Action::Cancel) => evs.send(Ev::Quit),
+        Some(Action::Wait) => {
+            evs.send(Ev::Nothing);
+            return;
+        }
+        Some(Action::Rest) => activities.start(Activity::Rest),
+        Some(Action::Explore) => activities.start(Activity::Explore),
+        Some(Action::Travel) => {
+            if let Ok(target) = cursor.single() {
+                activities.start(Activity::Travel(*target));
+            }
+        }
+        Some(Action::TravelToStairs) => match known_stairs(&world) {
+            Some(stairs) => activities.start(Activity::Travel(stairs)),
+            None => log.send(LogMessage(
+                LogCategory::Status,
+                "You haven't found the stairs yet.".into(),
+            )),
+        },
+        Some(action) => {
+            if let Some(step) = action.direction() {
+                new_pos.x += step.x;
+                new_pos.y += step.y;
             }
-            Some(KeyCode::Key1) => inventory.selected = Some(0),
-            Some(KeyCode::Key2) => inventory.selected = Some(1),
-            Some(KeyCode::Key3) => inventory.selected = Some(2),
-            Some(KeyCode::Key4) => inventory.selected = Some(3),
-            Some(KeyCode::Key5) => inventory.selected = Some(4),
-            Some(KeyCode::Escape) => evs.send(Ev::Quit),
-            _ => {}
         }
+        None => {}
     }
 
-    let cursor = *cursor.single().unwrap();
-    if world.tiles[cursor].contains(TileFlags::IN_VIEW) {
-        if let Some(index) = inventory.selected {
-            if let Some(item) = inventory.inventory[index] {
-                if buttons.just_pressed(MouseButton::Left) {
-                    match items.get(item).unwrap().2 {
-                        Item::HealthPotion => {
-                            if let Some(e) = world.entities[cursor]
-                                .iter()
-                                .find(|e| healthy_entities.get(**e).is_ok())
-                            {
-                                evs.send(Ev::Heal(*e, 4));
-                                inventory.inventory[index] = None;
-                                inventory.selected = None;
-                            }
-                        }
-                        Item::ScrollOfLightning => {
-                            if let Some(e) = world.entities[cursor]
-                                .iter()
-                                .find(|e| healthy_entities.get(**e).is_ok())
-                            {
-                                evs.send(Ev::Attack(player_entity, *e, 2));
-                                inventory.inventory[index] = None;
-                                inventory.selected = None;
-                            }
-                        }
-                        Item::ScrollOfParalysis => {
-                            if let Some(e) = world.entities[cursor]
-                                .iter()
-                                .find(|e| controllers.get(**e).is_ok())
-                            {
-                                evs.send(Ev::Paralyze(*e, 4));
-                                inventory.inventory[index] = None;
-                                inventory.selected = None;
-                            }
-                        }
-                        Item::ScrollOfFireball => {
-                            for x in -1..=1 {
-                                for y in -1..=1 {
-                                    if let Some(e) = world.entities[[cursor.x + x, cursor.y + y]]
-                                        .iter()
-                                        .find(|e| healthy_entities.get(**e).is_ok())
-                                    {
-                                        evs.send(Ev::Attack(player_entity, *e, 1));
-                                        inventory.inventory[index] = None;
-                                        inventory.selected = None;
-                                    }
-                                }
-                            }
-                        }
-                        Item::Sword => {
-                            if world.entities[cursor].contains(&player_entity) {
-                                inventory.weapon = Some(item);
-                                inventory.selected = None;
-                            }
-                        }
-                        Item::WarAxe => {
-                            if world.entities[cursor].contains(&player_entity) {
-                                inventory.weapon = Some(item);
-                                inventory.selected = None;
-                            }
-                        }
-                        Item::Armor => {
-                            if world.entities[cursor].contains(&player_entity) {
-                                inventory.armor = Some(item);
-                                inventory.selected = None;
-                            }
-                        }
-                    }
-                } else if buttons.just_pressed(MouseButton::Right) {
-                    evs.send(Ev::DropItem(player_entity, item, cursor));
-                    if inventory.armor == inventory.inventory[index] {
-                        inventory.armor = None;
-                    } else if inventory.weapon == inventory.inventory[index] {
-                        inventory.weapon = None;
-                    }
-                    inventory.inventory[index] = None;
-                    inventory.selected = None;
-                }
-            }
+    if activities.current().is_some() {
+        match activities.step(&world, &inventory) {
+            Ok(ev) => evs.send(ev),
+            Err(Some(message)) => log.send(LogMessage(LogCategory::Status, message.into())),
+            Err(None) => {}
         }
+        return;
     }
 
-    if let Some(i) = inventory.selected {
-        if inventory.inventory[i].is_none() {
+    // Aiming and using the item happens in the targeting state.
+    if let Some(index) = inventory.selected {
+        if inventory.inventory[index].is_some() {
+            let _ = app_state.push(AppState::Targeting);
+        } else {
             inventory.selected = None;
         }
     }
 
-    if *position != new_pos {
-        inventory.selected = None;
-
-        if new_pos == world.stairs {
+    if *position != new_pos && world.neighbours(*position).contains(&new_pos) {
+        if Some(new_pos) == world.stairs {
             evs.send(Ev::Descend);
+        } else if Some(new_pos) == world.up_stairs {
+            evs.send(Ev::Ascend);
         } else if world.tiles[new_pos].contains(TileFlags::BLOCKS_MOVEMENT) {
             for &entity in &world.entities[new_pos] {
                 if let Ok(()) = healthy_entities.get(entity) {
-                    let damage = match inventory.weapon.map(|item| items.get(item).unwrap().2) {
-                        Some(Item::WarAxe) => 3,
-                        Some(Item::Sword) => 2,
-                        _ => 1,
-                    };
-                    evs.send(Ev::Attack(player_entity, entity, damage));
+                    evs.send(Ev::Attack(player_entity, entity));
                 }
             }
         } else {
@@ -382,69 +651,181 @@ fn player_control(
 }
 
 fn enemy_ai(
-    enemy: Query<(Entity, &GridPosition), (With<EnemyAI>, With<Initiative>, Without<Paralyzed>)>,
+    mut enemy: Query<
+        (
+            Entity,
+            &GridPosition,
+            &ViewRadius,
+            &mut Awareness,
+            &Name,
+            &EnemyAI,
+            &StatusEffects,
+            &Health,
+        ),
+        With<Initiative>,
+    >,
     player: Query<(Entity, &GridPosition), With<Player>>,
     world: Res<WorldMap>,
-    inventory: Res<GameData>,
+    mut maps: ResMut<PlayerMaps>,
     mut evs: EventWriter<Ev>,
+    mut log: EventWriter<LogMessage>,
 ) {
-    let (enemy, position) = match enemy.single() {
-        Ok(e) => e,
-        Err(QuerySingleError::NoEntities(_)) => return,
-        Err(QuerySingleError::MultipleEntities(_)) => panic!(),
+    let (enemy, position, radius, mut awareness, name, ai, statuses, health) =
+        match enemy.single_mut() {
+            Ok((.., statuses, _)) if statuses.skips_turn() => return,
+            Ok(e) => e,
+            Err(QuerySingleError::NoEntities(_)) => return,
+            Err(QuerySingleError::MultipleEntities(_)) => panic!(),
+        };
+    let (player, player_pos) = player.single().unwrap();
+
+    // Unaware monsters only notice the player up close, so they can be sneaked past.
+    let radius = statuses.view_radius(radius.0);
+    let radius = match *awareness {
+        Awareness::Unaware => radius / 2,
+        Awareness::Alerted { .. } => radius,
     };
+    let (dx, dy) = (player_pos.x - position.x, player_pos.y - position.y);
+    let sees_player = dx * dx + dy * dy <= radius * radius + radius
+        && world.line_of_sight(*position, *player_pos);
 
-    if world.tiles[*position].contains(TileFlags::IN_VIEW) {
-        let (player, player_pos) = player.single().unwrap();
-        if let Some((path, _)) = world.pathfind(*position, *player_pos) {
-            if path[1] == *player_pos {
-                let damage = if inventory.armor.is_some() { 1 } else { 2 };
-                evs.send(Ev::Attack(enemy, player, damage));
-            } else if !world.tiles[path[1]].contains(TileFlags::BLOCKS_MOVEMENT) {
-                evs.send(Ev::Move(enemy, *position, path[1]));
-            } else {
-                evs.send(Ev::Nothing);
+    let target = if sees_player {
+        if matches!(*awareness, Awareness::Unaware)
+            && world.tiles[*position].contains(TileFlags::IN_VIEW)
+        {
+            log.send(LogMessage(
+                LogCategory::Combat,
+                format!("The {} notices you!", name.0),
+            ));
+        }
+        *awareness = Awareness::Alerted {
+            last_seen: *player_pos,
+            memory: Awareness::MEMORY,
+        };
+        *player_pos
+    } else if let Awareness::Alerted { last_seen, memory } = *awareness {
+        *awareness = if memory > 1 {
+            Awareness::Alerted {
+                last_seen,
+                memory: memory - 1,
             }
         } else {
-            evs.send(Ev::Nothing);
-        }
+            Awareness::Unaware
+        };
+        last_seen
     } else {
         evs.send(Ev::Nothing);
-    }
-}
+        return;
+    };
 
-fn paralyzed(
-    mut paralyzed: Query<(Entity, &mut Paralyzed), With<Initiative>>,
-    mut evs: EventWriter<Ev>,
-    mut commands: Commands,
-) {
-    if let Ok((entity, mut paralyzed)) = paralyzed.single_mut() {
-        paralyzed.0 -= 1;
-        if paralyzed.0 == 0 {
-            commands.entity(entity).remove::<Paralyzed>();
-        } else {
-            evs.send(Ev::Nothing);
+    let fleeing = health.current <= ai.flees_at && ai.ai != AIType::Sentry;
+    let free = |pos: GridPosition| !world.tiles[pos].contains(TileFlags::BLOCKS_MOVEMENT);
+    let next = if fleeing {
+        // Cornered monsters fight back.
+        maps.flee.downhill(&world, *position, free).or_else(|| {
+            Some(*player_pos).filter(|pos| sees_player && world.neighbours(*position).contains(pos))
+        })
+    } else if target == *player_pos {
+        maps.chase
+            .downhill(&world, *position, |pos| pos == *player_pos || free(pos))
+    } else {
+        maps.way_to(&world, target)
+            .downhill(&world, *position, free)
+    };
+
+    match next {
+        Some(next) if next == *player_pos => evs.send(Ev::Attack(enemy, player)),
+        Some(next)
+            if ai.ai != AIType::Sentry
+                && !world.tiles[next].contains(TileFlags::BLOCKS_MOVEMENT) =>
+        {
+            evs.send(Ev::Move(enemy, *position, next))
         }
+        _ => evs.send(Ev::Nothing),
     }
 }
 
+/// The components of creatures that `handle_evs` reads and changes.
+#[derive(SystemParam)]
+pub struct Creatures<'a> {
+    positions: Query<'a, &'static mut GridPosition>,
+    healthy: Query<'a, &'static mut Health>,
+    names: Query<'a, &'static Name>,
+    statuses: Query<'a, &'static mut StatusEffects>,
+    stats: Query<'a, &'static CombatStats>,
+    visible: Query<'a, &'static mut Visible>,
+    player: Query<'a, (), With<Player>>,
+    boss: Query<'a, (), With<Boss>>,
+}
+
+/// Where the run is at: its data, whose turn it is, the dice and the app state.
+#[derive(SystemParam)]
+pub struct Progress<'a> {
+    data: ResMut<'a, GameData>,
+    scheduler: ResMut<'a, Scheduler>,
+    current: Query<'a, Entity, With<Initiative>>,
+    rng: ResMut<'a, GameRng>,
+    app_state: ResMut<'a, State<AppState>>,
+}
+
 fn handle_evs(
     mut events: EventReader<Ev>,
-    mut positions: Query<&mut GridPosition>,
-    mut healthy: Query<&mut Health>,
+    creatures: Creatures,
+    progress: Progress,
     mut world: ResMut<WorldMap>,
-    names: Query<&Name>,
-    player: Query<(), With<Player>>,
+    items: ItemTemplates,
     mut log: EventWriter<LogMessage>,
-    mut app_state: ResMut<State<AppState>>,
-    mut data: ResMut<GameData>,
-    mut visible: Query<&mut Visible>,
     mut commands: Commands,
-    mut order: ResMut<InitiativeOrder>,
 ) {
+    let Creatures {
+        mut positions,
+        mut healthy,
+        names,
+        mut statuses,
+        stats,
+        mut visible,
+        player,
+        boss,
+    } = creatures;
+    let Progress {
+        mut data,
+        mut scheduler,
+        current,
+        mut rng,
+        mut app_state,
+    } = progress;
+    // Equipment only counts for the player.
+    let equipment = items.equipment(&data);
+    let stats_of = |entity: Entity| {
+        let base = stats.get(entity).copied().unwrap_or_default();
+        if player.get(entity).is_ok() {
+            base + equipment
+        } else {
+            base
+        }
+    };
+
     let mut evs: VecDeque<Ev> = VecDeque::new();
     evs.extend(events.iter());
 
+    if let (Ok(actor), Some(cost)) = (current.single(), evs.iter().filter_map(Ev::cost).max()) {
+        scheduler.spend(actor, cost);
+        if player.get(actor).is_ok() {
+            data.turn += 1;
+            // Natural healing, slow enough that resting takes a while.
+            if data.turn.is_multiple_of(GameData::REGEN_TURNS) {
+                if let Ok(mut hp) = healthy.get_mut(actor) {
+                    hp.current = i32::min(hp.max, hp.current + 1);
+                }
+            }
+        }
+
+        // Statuses tick once per action of the affected entity.
+        if let Ok(actor_statuses) = statuses.get_mut(actor) {
+            evs.extend(actor_statuses.0.iter().map(|s| Ev::TickStatus(actor, *s)));
+        }
+    }
+
     let mut next_app_state = None;
     if !evs.is_empty() {
         next_app_state = Some(AppState::DungeonCrawl(TurnState::WorldUpdate));
@@ -465,27 +846,103 @@ fn handle_evs(
                     *pos = new_pos;
                 }
             }
-            Ev::Attack(attacker, attackee, damage) => {
-                log.send(LogMessage(format!(
-                    "{} attacks {}, dealing {} damage!",
-                    names.get(attacker).unwrap().capitalized(),
-                    names.get(attackee).unwrap().0,
-                    damage
-                )));
+            Ev::Attack(attacker, attackee) => {
+                let (attack, defense) = (stats_of(attacker), stats_of(attackee));
+                let roll = rng.0.gen_range(1..=20);
+
+                if roll <= CombatStats::FUMBLE
+                    || (roll < CombatStats::CRITICAL
+                        && roll + attack.accuracy - defense.evasion < CombatStats::TO_HIT)
+                {
+                    log.send(LogMessage(
+                        LogCategory::Combat,
+                        format!(
+                            "{} attacks {}, but misses.",
+                            names.get(attacker).unwrap().capitalized(),
+                            names.get(attackee).unwrap().0,
+                        ),
+                    ));
+                    continue;
+                }
+
+                let mut damage = attack.power;
+                if roll >= CombatStats::CRITICAL {
+                    log.send(LogMessage(LogCategory::Combat, "Critical hit!".into()));
+                    damage *= 2;
+                }
+                evs.push_back(Ev::Damage(
+                    DamageSource::Creature(attacker),
+                    attackee,
+                    i32::max(damage - defense.defense, 1),
+                ));
+            }
+            Ev::Damage(source, attackee, damage) => {
+                // It might have already died from an earlier event this turn.
+                if healthy.get_mut(attackee).unwrap().current <= 0 {
+                    continue;
+                }
+
+                // The creature that dealt the damage to someone else.
+                let attacker = match source {
+                    DamageSource::Creature(attacker) if attacker != attackee => Some(attacker),
+                    _ => None,
+                };
+                if let Some(attacker) = attacker {
+                    log.send(LogMessage(
+                        LogCategory::Combat,
+                        format!(
+                            "{} attacks {}, dealing {} damage!",
+                            names.get(attacker).unwrap().capitalized(),
+                            names.get(attackee).unwrap().0,
+                            damage
+                        ),
+                    ));
+                } else {
+                    log.send(LogMessage(
+                        LogCategory::Combat,
+                        format!(
+                            "{} takes {} damage!",
+                            names.get(attackee).unwrap().capitalized(),
+                            damage
+                        ),
+                    ));
+                }
 
                 let health = &mut healthy.get_mut(attackee).unwrap().current;
                 *health -= damage;
 
                 if *health <= 0 {
-                    log.send(LogMessage(format!(
-                        "{} died!",
-                        names.get(attackee).unwrap().capitalized()
-                    )));
+                    log.send(LogMessage(
+                        LogCategory::Combat,
+                        format!("{} died!", names.get(attackee).unwrap().capitalized()),
+                    ));
 
-                    if player.get(attacker).is_ok() {
+                    if player.get(attackee).is_ok() {
+                        data.outcome = Some(match source {
+                            DamageSource::Status(status) => RunOutcome::Succumbed { status },
+                            DamageSource::Creature(_) => RunOutcome::Died {
+                                killer: attacker.map(|a| names.get(a).unwrap().0.clone()),
+                            },
+                        });
+                    } else if boss.get(attackee).is_ok() {
+                        log.send(LogMessage(
+                            LogCategory::System,
+                            "You are victorious!".into(),
+                        ));
+                        data.outcome = Some(RunOutcome::Won {
+                            boss: names.get(attackee).unwrap().0.clone(),
+                        });
+                        next_app_state = Some(AppState::EndOfRun);
+                    }
+
+                    if let Some(attacker) = attacker.filter(|a| player.get(*a).is_ok()) {
+                        *data
+                            .kills
+                            .entry(names.get(attackee).unwrap().0.clone())
+                            .or_default() += 1;
                         data.current_xp += 1;
                         if data.current_xp >= data.needed_xp {
-                            log.send(LogMessage("You level up!".into()));
+                            log.send(LogMessage(LogCategory::System, "You level up!".into()));
 
                             data.current_xp = 0;
                             data.needed_xp += 2;
@@ -504,10 +961,10 @@ fn handle_evs(
                 for slot in &mut data.inventory {
                     if slot.is_none() {
                         *slot = Some(item);
-                        log.send(LogMessage(format!(
-                            "You pick up {}.",
-                            names.get(item).unwrap().0,
-                        )));
+                        log.send(LogMessage(
+                            LogCategory::Loot,
+                            format!("You pick up {}.", names.get(item).unwrap().0,),
+                        ));
                         evs.push_back(Ev::RemoveFromMap(item));
                         break;
                     }
@@ -515,25 +972,68 @@ fn handle_evs(
             }
             Ev::DropItem(_, item, position) => {
                 if world.tiles[position].contains(TileFlags::BLOCKS_MOVEMENT) {
-                    log.send(LogMessage(format!(
-                        "{} slams into the wall.",
-                        names.get(item).unwrap().capitalized(),
-                    )));
+                    log.send(LogMessage(
+                        LogCategory::Loot,
+                        format!(
+                            "{} slams into the wall.",
+                            names.get(item).unwrap().capitalized(),
+                        ),
+                    ));
                     evs.push_back(Ev::Despawn(item));
                 } else {
-                    log.send(LogMessage(format!(
-                        "{} lands on the floor.",
-                        names.get(item).unwrap().capitalized(),
-                    )));
+                    log.send(LogMessage(
+                        LogCategory::Loot,
+                        format!(
+                            "{} lands on the floor.",
+                            names.get(item).unwrap().capitalized(),
+                        ),
+                    ));
                     evs.push_back(Ev::AddToMap(item, position));
                 }
             }
+            Ev::UseItem(user, item, target) => {
+                log.send(LogMessage(
+                    LogCategory::Loot,
+                    format!(
+                        "{} uses {}.",
+                        names.get(user).unwrap().capitalized(),
+                        names.get(item).unwrap().0,
+                    ),
+                ));
+
+                let template = items.get(item);
+                let mut targets = vec![];
+                if template.targeting == Targeting::OnSelf {
+                    targets.push(user);
+                } else {
+                    for position in template.targeting.area(&world, target) {
+                        let tile = &world.entities[position];
+                        targets.extend(tile.iter().filter(|e| healthy.get_mut(**e).is_ok()));
+                    }
+                }
+
+                for &entity in &targets {
+                    for effect in &template.effects {
+                        evs.push_back(match *effect {
+                            Effect::Heal(amount) => Ev::Heal(entity, amount),
+                            Effect::Damage(damage) => {
+                                Ev::Damage(DamageSource::Creature(user), entity, damage)
+                            }
+                            Effect::Status(status) => Ev::AddStatus(entity, status),
+                        });
+                    }
+                }
+                evs.push_back(Ev::Despawn(item));
+            }
             Ev::Heal(entity, amount) => {
-                log.send(LogMessage(format!(
-                    "{} is healed by {} health.",
-                    names.get(entity).unwrap().capitalized(),
-                    amount
-                )));
+                log.send(LogMessage(
+                    LogCategory::Status,
+                    format!(
+                        "{} is healed by {} health.",
+                        names.get(entity).unwrap().capitalized(),
+                        amount
+                    ),
+                ));
                 let mut hp = healthy.get_mut(entity).unwrap();
                 hp.current = i32::min(hp.max, hp.current + amount);
             }
@@ -553,27 +1053,81 @@ fn handle_evs(
                 visible.get_mut(entity).unwrap().is_visible = true;
             }
             Ev::RemoveFromInitiative(entity) => {
-                let i = order.0.iter().position(|x| *x == entity).unwrap();
-                order.0.remove(i);
+                scheduler.remove(entity);
                 commands.entity(entity).remove::<Initiative>();
             }
             Ev::Despawn(entity) => {
                 if player.get(entity).is_ok() {
-                    next_app_state = Some(AppState::DungeonCrawlExitToMenu);
+                    next_app_state = Some(AppState::EndOfRun);
                 } else {
                     commands.entity(entity).despawn();
                 }
             }
-            Ev::Paralyze(entity, duration) => {
-                commands.entity(entity).insert(Paralyzed(duration));
+            Ev::AddStatus(entity, status) => {
+                if let Ok(mut statuses) = statuses.get_mut(entity) {
+                    let name = names.get(entity).unwrap().capitalized();
+                    match statuses.add(status) {
+                        Some(cancelled) => log.send(LogMessage(
+                            LogCategory::Status,
+                            format!("{} is no longer {}.", name, cancelled.adjective()),
+                        )),
+                        None => log.send(LogMessage(
+                            LogCategory::Status,
+                            format!("{} is {}!", name, status.kind.adjective()),
+                        )),
+                    }
+                }
+            }
+            Ev::TickStatus(entity, status) => {
+                match status.kind {
+                    StatusKind::Poison => evs.push_back(Ev::Damage(
+                        DamageSource::Status(status.kind),
+                        entity,
+                        status.potency,
+                    )),
+                    StatusKind::Regeneration => evs.push_back(Ev::Heal(entity, status.potency)),
+                    _ => {}
+                }
+
+                let mut statuses = statuses.get_mut(entity).unwrap();
+                if let Some(s) = statuses.0.iter_mut().find(|s| s.kind == status.kind) {
+                    s.duration -= 1;
+                    if s.duration <= 0 {
+                        evs.push_back(Ev::RemoveStatus(entity, status.kind));
+                    }
+                }
+            }
+            Ev::RemoveStatus(entity, kind) => {
+                let mut statuses = statuses.get_mut(entity).unwrap();
+                if statuses.has(kind) {
+                    statuses.remove(kind);
+                    log.send(LogMessage(
+                        LogCategory::Status,
+                        format!(
+                            "{} is no longer {}.",
+                            names.get(entity).unwrap().capitalized(),
+                            kind.adjective()
+                        ),
+                    ));
+                }
             }
             Ev::Quit => {
                 next_app_state = Some(AppState::DungeonCrawlExitToMenu);
             }
             Ev::Descend => {
-                log.send(LogMessage("You descend to the next dungeon floor".into()));
+                log.send(LogMessage(
+                    LogCategory::System,
+                    "You descend to the next dungeon floor".into(),
+                ));
                 next_app_state = Some(AppState::DungeonCrawlDescend);
             }
+            Ev::Ascend => {
+                log.send(LogMessage(
+                    LogCategory::System,
+                    "You climb up to the previous dungeon floor".into(),
+                ));
+                next_app_state = Some(AppState::DungeonCrawlAscend);
+            }
         }
     }
 
@@ -584,18 +1138,21 @@ fn handle_evs(
 
 pub fn cleanup(
     // Player might have died so it has additional check
-    q: Query<Entity, Or<(With<MyCanvas>, With<GridPosition>, With<Player>)>>,
+    q: Query<Entity, Or<(With<GridPosition>, With<Player>)>>,
     mut commands: Commands,
     mut data: ResMut<GameData>,
-    player: Query<&Health, With<Player>>,
+    mut activity: ResMut<PlayerActivity>,
+    player: Query<(&Health, &StatusEffects), With<Player>>,
 ) {
     for e in q.iter() {
         commands.entity(e).despawn_recursive();
     }
-    commands.remove_resource::<InitiativeOrder>();
+    activity.stop();
+    commands.remove_resource::<Scheduler>();
     commands.remove_resource::<WorldMap>();
-    data.previous_hp = Some(*player.single().unwrap());
-    data.floor += 1;
+    let (health, statuses) = player.single().unwrap();
+    data.previous_hp = Some(*health);
+    data.previous_statuses = statuses.clone();
 }
 
 pub fn cleanup_log_and_inventory(mut commands: Commands, inventory: Res<GameData>) {
@@ -604,4 +1161,71 @@ pub fn cleanup_log_and_inventory(mut commands: Commands, inventory: Res<GameData
         commands.entity(e).despawn();
     }
     commands.insert_resource(GameData::default());
+    commands.insert_resource(save::Dungeon::default());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Who acts in the next `turns` turns, each spending a full action.
+    fn turn_order(
+        scheduler: &mut Scheduler,
+        speed: impl Fn(Entity) -> i32,
+        turns: usize,
+    ) -> Vec<Entity> {
+        (0..turns)
+            .map(|_| {
+                let actor = scheduler.next(&speed).unwrap();
+                scheduler.spend(actor, Scheduler::ACTION_COST);
+                actor
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ties_go_to_whoever_joined_first() {
+        let (a, b) = (Entity::new(0), Entity::new(1));
+        let mut scheduler = Scheduler::default();
+        scheduler.add(b);
+        scheduler.add(a);
+        scheduler.add(b);
+        assert_eq!(turn_order(&mut scheduler, |_| 100, 4), vec![b, a, b, a]);
+    }
+
+    #[test]
+    fn faster_actors_act_more_often() {
+        let (fast, slow) = (Entity::new(0), Entity::new(1));
+        let mut scheduler = Scheduler::default();
+        scheduler.add(slow);
+        scheduler.add(fast);
+        let speed = |e| if e == fast { 200 } else { 100 };
+        let order = turn_order(&mut scheduler, speed, 30);
+        assert_eq!(order.iter().filter(|e| **e == fast).count(), 20);
+    }
+
+    #[test]
+    fn cheap_actions_act_again_sooner() {
+        let (a, b) = (Entity::new(0), Entity::new(1));
+        let mut scheduler = Scheduler::default();
+        scheduler.add(a);
+        scheduler.add(b);
+        assert_eq!(scheduler.next(|_| 100), Some(a));
+        scheduler.spend(a, Scheduler::ACTION_COST / 2);
+        assert_eq!(scheduler.next(|_| 100), Some(b));
+        scheduler.spend(b, Scheduler::ACTION_COST);
+        assert_eq!(scheduler.next(|_| 100), Some(a));
+    }
+
+    #[test]
+    fn removed_actors_stop_acting() {
+        let (a, b) = (Entity::new(0), Entity::new(1));
+        let mut scheduler = Scheduler::default();
+        scheduler.add(a);
+        scheduler.add(b);
+        scheduler.remove(a);
+        assert_eq!(turn_order(&mut scheduler, |_| 100, 2), vec![b, b]);
+        scheduler.remove(b);
+        assert_eq!(scheduler.next(|_| 100), None);
+    }
 }
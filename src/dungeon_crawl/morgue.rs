@@ -0,0 +1,108 @@
+use super::{log::Logs, GameData, Name, Player};
+use crate::{
+    storage,
+    world_map::{GridPosition, TileFlags, WorldMap},
+};
+use bevy::prelude::*;
+use std::fmt::Write;
+
+/// Writes a plain text summary of the run that just ended, so runs can be compared
+/// after the game throws the state away.
+pub fn write_morgue(
+    data: Res<GameData>,
+    logs: Res<Logs>,
+    world: Res<WorldMap>,
+    names: Query<&Name>,
+    player: Query<&GridPosition, With<Player>>,
+) {
+    let name_of = |item: Option<Entity>| {
+        item.and_then(|item| names.get(item).ok())
+            .map_or(String::from("-"), |name| name.0.clone())
+    };
+
+    let mut morgue = String::new();
+    let _ = writeln!(morgue, "Seed: {}", data.seed);
+    if let Some(outcome) = &data.outcome {
+        let _ = writeln!(morgue, "{} on floor {}", outcome, data.floor);
+    }
+    let _ = writeln!(
+        morgue,
+        "Level: {} (XP {}/{})",
+        data.level, data.current_xp, data.needed_xp
+    );
+    let _ = writeln!(morgue, "Turns: {}", data.turn);
+
+    let _ = writeln!(morgue, "\nWeapon: {}", name_of(data.weapon));
+    let _ = writeln!(morgue, "Armor: {}", name_of(data.armor));
+    let _ = writeln!(morgue, "Inventory:");
+    for item in data.inventory.iter().filter(|i| i.is_some()) {
+        let _ = writeln!(morgue, "  {}", name_of(*item));
+    }
+
+    let _ = writeln!(morgue, "\nKills:");
+    for (name, count) in &data.kills {
+        let _ = writeln!(morgue, "  {:>3} {}", count, name);
+    }
+    let _ = writeln!(morgue, "  {:>3} total", data.kills.values().sum::<u32>());
+
+    let _ = writeln!(morgue, "\nFloor {}:", data.floor);
+    morgue.push_str(&map_dump(&world, player.single().ok().copied()));
+
+    let _ = writeln!(morgue, "\nMessages:");
+    for message in logs.0.iter().rev() {
+        let _ = writeln!(morgue, "[{}] {}", message.turn, message.text);
+    }
+
+    if let Err(err) = storage::write(&morgue_name(data.seed), &morgue) {
+        error!("Couldn't write the morgue file: {}", err);
+    }
+}
+
+/// Explored part of the floor, `@` is the player, `>` and `<` are the stairs.
+fn map_dump(world: &WorldMap, player: Option<GridPosition>) -> String {
+    let size = world.tiles.size();
+    let explored = |x: i32, y: i32| world.tiles[[x, y]].contains(TileFlags::EXPLORED);
+
+    // `Iterator::any` is spelled out, on ranges the prelude picks `Reflect::any` instead.
+    let columns: Vec<i32> = (0..size.x)
+        .filter(|&x| Iterator::any(&mut (0..size.y), |y| explored(x, y)))
+        .collect();
+    let rows: Vec<i32> = (0..size.y)
+        .filter(|&y| Iterator::any(&mut (0..size.x), |x| explored(x, y)))
+        .collect();
+    let (min_x, max_x) = match (columns.first(), columns.last()) {
+        (Some(&min), Some(&max)) => (min, max),
+        _ => return String::new(),
+    };
+
+    let mut dump = String::new();
+    // Rows go from the top of the screen, which is the highest y.
+    for &y in rows.iter().rev() {
+        for x in min_x..=max_x {
+            let position = Some(GridPosition { x, y });
+            dump.push(if position == player {
+                '@'
+            } else if !explored(x, y) {
+                ' '
+            } else if position == world.stairs {
+                '>'
+            } else if position == world.up_stairs {
+                '<'
+            } else if world.tiles[[x, y]].contains(TileFlags::BLOCKS_PATHFINDING) {
+                '#'
+            } else {
+                '.'
+            });
+        }
+        dump.push('\n');
+    }
+    dump
+}
+
+/// The first free `morgue-<seed>-<n>.txt`, so replaying a seed keeps the earlier runs.
+fn morgue_name(seed: u64) -> String {
+    (1..)
+        .map(|n| format!("morgue-{}-{}.txt", seed, n))
+        .find(|name| !storage::exists(name))
+        .unwrap()
+}
@@ -0,0 +1,453 @@
+use super::{
+    log::{LogEntry, Logs},
+    Boss, EnemyAI, GameData, Health, Item, Name, Player, Scheduler, Status, StatusEffects,
+};
+use crate::{
+    bundles::{EnemyBundle, ItemBundle, PlayerBundle, SpriteMaterials},
+    items::ItemTable,
+    monsters::MonsterTable,
+    storage,
+    world_map::{Array2D, BlocksMovement, GridPosition, Tile, TileFactory, TileFlags, WorldMap},
+    AppState,
+};
+#[cfg(not(feature = "headless"))]
+use bevy::{app::AppExit, window::WindowCloseRequested};
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const STORAGE_NAME: &str = "savegame.ron";
+
+#[derive(Serialize, Deserialize)]
+enum EntityKind {
+    Wall,
+    Floor,
+    Stairs,
+    UpStairs,
+    Player,
+    Enemy,
+    Item(Item),
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedEntity {
+    kind: EntityKind,
+    position: Option<GridPosition>,
+    health: Option<Health>,
+    #[serde(default)]
+    statuses: Vec<Status>,
+    name: String,
+}
+
+/// A floor exactly as the player left it.
+#[derive(Serialize, Deserialize)]
+pub struct SavedFloor {
+    explored: Array2D<bool>,
+    stairs: Option<GridPosition>,
+    up_stairs: Option<GridPosition>,
+    entities: Vec<SavedEntity>,
+}
+
+/// Floors the player isn't currently on, by their number.
+#[derive(Default)]
+pub struct Dungeon(pub BTreeMap<u32, SavedFloor>);
+
+#[derive(Serialize, Deserialize)]
+struct SaveData {
+    seed: u64,
+    #[serde(default)]
+    turn: u32,
+    floor: u32,
+    level: u32,
+    current_xp: u32,
+    needed_xp: u32,
+    #[serde(default)]
+    kills: BTreeMap<String, u32>,
+
+    // Equipment is stored as an index into the inventory.
+    inventory: [Option<SavedEntity>; 5],
+    armor: Option<usize>,
+    weapon: Option<usize>,
+
+    current: SavedFloor,
+    dungeon: BTreeMap<u32, SavedFloor>,
+    log: Vec<LogEntry>,
+}
+
+/// The components that tell which `EntityKind` an entity is.
+type KindQuery<'a> = Query<
+    'a,
+    (
+        Option<&'static Tile>,
+        Option<&'static BlocksMovement>,
+        Option<&'static Player>,
+        Option<&'static EnemyAI>,
+        Option<&'static Item>,
+    ),
+>;
+
+/// Turns the current floor's entities into their saved form.
+#[derive(SystemParam)]
+pub struct FloorSnapshot<'a> {
+    world: Res<'a, WorldMap>,
+    kinds: KindQuery<'a>,
+    stats: Query<
+        'a,
+        (
+            &'static Name,
+            Option<&'static Health>,
+            Option<&'static StatusEffects>,
+        ),
+    >,
+}
+
+impl<'a> FloorSnapshot<'a> {
+    fn entity(&self, entity: Entity, position: Option<GridPosition>) -> Option<SavedEntity> {
+        let kind = match self.kinds.get(entity).ok()? {
+            (_, _, Some(Player), _, _) => EntityKind::Player,
+            (_, _, _, Some(_), _) => EntityKind::Enemy,
+            (_, _, _, _, Some(item)) => EntityKind::Item(item.clone()),
+            (Some(Tile), Some(BlocksMovement), _, _, _) => EntityKind::Wall,
+            (Some(Tile), _, _, _, _) if position.is_some() && position == self.world.stairs => {
+                EntityKind::Stairs
+            }
+            (Some(Tile), _, _, _, _) if position.is_some() && position == self.world.up_stairs => {
+                EntityKind::UpStairs
+            }
+            (Some(Tile), _, _, _, _) => EntityKind::Floor,
+            _ => return None,
+        };
+        let (name, health, statuses) = self.stats.get(entity).ok()?;
+        Some(SavedEntity {
+            kind,
+            position,
+            health: health.copied(),
+            statuses: statuses.map_or(vec![], |s| s.0.clone()),
+            name: name.0.clone(),
+        })
+    }
+
+    fn floor(&self) -> SavedFloor {
+        let size = self.world.tiles.size();
+        let mut explored = Array2D::with_size(size.x, size.y);
+        let mut entities = vec![];
+        for x in 0..size.x {
+            for y in 0..size.y {
+                explored[[x, y]] = self.world.tiles[[x, y]].contains(TileFlags::EXPLORED);
+                for &entity in &self.world.entities[[x, y]] {
+                    entities.extend(self.entity(entity, Some(GridPosition { x, y })));
+                }
+            }
+        }
+
+        SavedFloor {
+            explored,
+            stairs: self.world.stairs,
+            up_stairs: self.world.up_stairs,
+            entities,
+        }
+    }
+}
+
+/// Spawns saved entities and floors back into the world.
+#[derive(SystemParam)]
+pub struct FloorSpawner<'a> {
+    materials: SpriteMaterials<'a>,
+    monsters: Res<'a, MonsterTable>,
+    items: Res<'a, ItemTable>,
+}
+
+impl<'a> FloorSpawner<'a> {
+    /// Spawns a saved entity, unless its monster or item is no longer in the data files.
+    fn entity(
+        &mut self,
+        commands: &mut Commands,
+        saved: &SavedEntity,
+        tile_factory: &TileFactory,
+        data: &GameData,
+    ) -> Option<Entity> {
+        let entity = match &saved.kind {
+            EntityKind::Wall | EntityKind::Floor | EntityKind::Stairs | EntityKind::UpStairs => {
+                let GridPosition { x, y } = saved.position.unwrap();
+                match saved.kind {
+                    EntityKind::Wall => tile_factory.wall(commands, x, y),
+                    EntityKind::Stairs => tile_factory.stairs(commands, x, y),
+                    EntityKind::UpStairs => tile_factory.up_stairs(commands, x, y),
+                    _ => tile_factory.floor(commands, x, y),
+                }
+            }
+            EntityKind::Player => commands
+                .spawn_bundle(PlayerBundle::new(&mut self.materials, data))
+                .id(),
+            EntityKind::Enemy => {
+                let template = match self.monsters.get(&saved.name) {
+                    Some(template) => template,
+                    None => {
+                        warn!(
+                            "Unknown monster {} in the saved game, leaving it out",
+                            saved.name
+                        );
+                        return None;
+                    }
+                };
+                let mut enemy =
+                    commands.spawn_bundle(EnemyBundle::new(template, &mut self.materials));
+                if template.boss {
+                    enemy.insert(Boss);
+                }
+                enemy.id()
+            }
+            EntityKind::Item(item) => {
+                let template = match self.items.get(&item.0) {
+                    Some(template) => template,
+                    None => {
+                        warn!("Unknown item {} in the saved game, leaving it out", item.0);
+                        return None;
+                    }
+                };
+                commands
+                    .spawn_bundle(ItemBundle::new(template, &mut self.materials))
+                    .id()
+            }
+        };
+
+        let mut e = commands.entity(entity);
+        e.insert(Name(saved.name.clone()));
+        match saved.position {
+            Some(position) => e.insert(position),
+            // Picked up items are hidden until they are dropped again.
+            None => e.insert(Visible {
+                is_visible: false,
+                is_transparent: true,
+            }),
+        };
+        if let Some(health) = saved.health {
+            e.insert(health);
+        }
+        if !saved.statuses.is_empty() {
+            e.insert(StatusEffects(saved.statuses.clone()));
+        }
+        Some(entity)
+    }
+
+    fn floor(&mut self, commands: &mut Commands, floor: &SavedFloor, data: &GameData) -> WorldMap {
+        let tile_factory = TileFactory::new(&mut self.materials);
+        let size = floor.explored.size();
+        let mut entities = Array2D::<Vec<Entity>>::with_size(size.x, size.y);
+        let mut tiles = Array2D::<TileFlags>::with_size(size.x, size.y);
+        for x in 0..size.x {
+            for y in 0..size.y {
+                if floor.explored[[x, y]] {
+                    tiles[[x, y]] = TileFlags::EXPLORED;
+                }
+            }
+        }
+
+        for saved in &floor.entities {
+            if let Some(entity) = self.entity(commands, saved, &tile_factory, data) {
+                entities[saved.position.unwrap()].push(entity);
+            }
+        }
+
+        WorldMap {
+            entities,
+            tile_factory,
+            tiles,
+            stairs: floor.stairs,
+            up_stairs: floor.up_stairs,
+        }
+    }
+}
+
+/// Keeps the floor the player is leaving in the `Dungeon`.
+pub fn store_floor(snapshot: FloorSnapshot, data: Res<GameData>, mut dungeon: ResMut<Dungeon>) {
+    let mut floor = snapshot.floor();
+    floor
+        .entities
+        .retain(|e| !matches!(e.kind, EntityKind::Player));
+    dungeon.0.insert(data.floor, floor);
+}
+
+/// Moves the player one floor down or up depending on the state. Floors that were
+/// already visited are restored from the `Dungeon`, new ones are generated.
+pub fn change_floor(
+    mut data: ResMut<GameData>,
+    mut dungeon: ResMut<Dungeon>,
+    mut app_state: ResMut<State<AppState>>,
+) {
+    let descending = *app_state.current() == AppState::DungeonCrawlDescend;
+    if descending {
+        data.floor += 1;
+    } else {
+        data.floor -= 1;
+    }
+
+    match dungeon.0.get_mut(&data.floor) {
+        Some(floor) => {
+            // Arrive on the other end of the stairs that were taken.
+            let position = if descending {
+                floor.up_stairs
+            } else {
+                floor.stairs
+            };
+            floor.entities.push(SavedEntity {
+                kind: EntityKind::Player,
+                position,
+                health: data.previous_hp,
+                statuses: data.previous_statuses.0.clone(),
+                name: String::from("player"),
+            });
+            app_state.set(AppState::LoadFloor).unwrap();
+        }
+        None => app_state.set(AppState::WorldGeneration).unwrap(),
+    }
+}
+
+/// Spawns the current floor back from the `Dungeon`.
+pub fn load_floor(
+    mut commands: Commands,
+    mut spawner: FloorSpawner,
+    data: Res<GameData>,
+    mut dungeon: ResMut<Dungeon>,
+    mut app_state: ResMut<State<AppState>>,
+) {
+    let floor = dungeon.0.remove(&data.floor).unwrap();
+    let world = spawner.floor(&mut commands, &floor, &data);
+    commands.insert_resource(world);
+    commands.insert_resource(Scheduler::default());
+    app_state.set(AppState::DungeonCrawlEnter).unwrap();
+}
+
+pub fn save_exists() -> bool {
+    storage::exists(STORAGE_NAME)
+}
+
+#[cfg(not(feature = "headless"))]
+pub fn delete_save() {
+    storage::remove(STORAGE_NAME);
+}
+
+/// Everything `save_game` stores.
+#[cfg(not(feature = "headless"))]
+#[derive(SystemParam)]
+pub struct SaveSources<'a> {
+    snapshot: FloorSnapshot<'a>,
+    data: Res<'a, GameData>,
+    dungeon: ResMut<'a, Dungeon>,
+    logs: Res<'a, Logs>,
+    player: Query<'a, &'static Health, With<Player>>,
+}
+
+#[cfg(not(feature = "headless"))]
+pub fn save_game(sources: SaveSources) {
+    let SaveSources {
+        snapshot,
+        data,
+        mut dungeon,
+        logs,
+        player,
+    } = sources;
+    match player.single() {
+        Ok(hp) if hp.current > 0 => {}
+        // Dead players don't get to continue.
+        _ => {
+            delete_save();
+            return;
+        }
+    }
+
+    let slot_of = |item: Option<Entity>| {
+        item.and_then(|item| data.inventory.iter().position(|i| *i == Some(item)))
+    };
+
+    let mut inventory: [Option<SavedEntity>; 5] = Default::default();
+    for (slot, item) in inventory.iter_mut().zip(data.inventory.iter()) {
+        *slot = item.and_then(|item| snapshot.entity(item, None));
+    }
+
+    let save = SaveData {
+        seed: data.seed,
+        turn: data.turn,
+        floor: data.floor,
+        level: data.level,
+        current_xp: data.current_xp,
+        needed_xp: data.needed_xp,
+        kills: data.kills.clone(),
+
+        inventory,
+        armor: slot_of(data.armor),
+        weapon: slot_of(data.weapon),
+
+        current: snapshot.floor(),
+        // The run ends after saving so the floors don't have to be kept.
+        dungeon: std::mem::take(&mut dungeon.0),
+        log: logs.0.iter().cloned().collect(),
+    };
+
+    let result = ron::to_string(&save)
+        .map_err(|e| e.to_string())
+        .and_then(|s| storage::write(STORAGE_NAME, &s));
+    if let Err(err) = result {
+        error!("Couldn't save the game: {}", err);
+    }
+}
+
+/// Saves the run when the game is closed in the middle of it, like leaving to the menu does.
+#[cfg(not(feature = "headless"))]
+pub fn save_on_exit(
+    mut exits: EventReader<AppExit>,
+    mut closes: EventReader<WindowCloseRequested>,
+    sources: SaveSources,
+) {
+    if exits.iter().count() + closes.iter().count() > 0 {
+        save_game(sources);
+    }
+}
+
+pub fn load_game(
+    mut commands: Commands,
+    mut spawner: FloorSpawner,
+    mut app_state: ResMut<State<AppState>>,
+) {
+    let save: SaveData = match storage::read(STORAGE_NAME)
+        .ok_or_else(|| String::from("there is no saved game"))
+        .and_then(|s| ron::from_str(&s).map_err(|e| e.to_string()))
+    {
+        Ok(save) => save,
+        Err(err) => {
+            error!("Couldn't load the game: {}", err);
+            app_state.set(AppState::WorldGeneration).unwrap();
+            return;
+        }
+    };
+
+    let mut data = GameData {
+        seed: save.seed,
+        turn: save.turn,
+        floor: save.floor,
+        level: save.level,
+        current_xp: save.current_xp,
+        needed_xp: save.needed_xp,
+        kills: save.kills,
+        ..Default::default()
+    };
+
+    let world = spawner.floor(&mut commands, &save.current, &data);
+
+    let mut inventory = [None; 5];
+    for (slot, saved) in inventory.iter_mut().zip(save.inventory.iter()) {
+        *slot = saved
+            .as_ref()
+            .and_then(|saved| spawner.entity(&mut commands, saved, &world.tile_factory, &data));
+    }
+    data.inventory = inventory;
+    data.armor = save.armor.and_then(|i| data.inventory[i]);
+    data.weapon = save.weapon.and_then(|i| data.inventory[i]);
+
+    commands.insert_resource(world);
+    commands.insert_resource(Scheduler::default());
+    commands.insert_resource(Dungeon(save.dungeon));
+    commands.insert_resource(Logs(save.log.into()));
+    commands.insert_resource(data);
+    app_state.set(AppState::DungeonCrawlEnter).unwrap();
+}
@@ -1,5 +1,7 @@
-use super::{EnemyAI, Initiative, InitiativeOrder, Player};
-use crate::world_map::{BlocksMovement, BlocksVision, Tile, TileFlags, WorldMap};
+use super::{Awareness, Initiative, Player, PlayerMaps, Scheduler, Speed, StatusEffects};
+use crate::world_map::{
+    BlocksMovement, BlocksVision, DijkstraMap, GridPosition, Tile, TileFlags, WorldMap,
+};
 use bevy::prelude::*;
 
 pub fn update_world_map(
@@ -44,24 +46,57 @@ pub fn update_world_map(
     }
 }
 
+pub fn update_player_maps(
+    world: Res<WorldMap>,
+    player: Query<&GridPosition, With<Player>>,
+    awareness: Query<&Awareness>,
+    mut maps: ResMut<PlayerMaps>,
+) {
+    // The maps of the last floor lead nowhere on this one.
+    if world.is_added() {
+        *maps = PlayerMaps::default();
+    }
+
+    let remembered: Vec<GridPosition> = awareness
+        .iter()
+        .filter_map(|awareness| match awareness {
+            Awareness::Alerted { last_seen, .. } => Some(*last_seen),
+            Awareness::Unaware => None,
+        })
+        .collect();
+    maps.last_seen.retain(|pos, _| remembered.contains(pos));
+
+    if let Ok(position) = player.single() {
+        if maps.built_from != Some(*position) {
+            maps.chase = DijkstraMap::new(&world, &[*position]);
+            maps.flee = maps.chase.inverted(&world);
+            maps.built_from = Some(*position);
+        }
+    }
+}
+
 pub fn handle_initiative(
-    mut order: ResMut<InitiativeOrder>,
+    mut scheduler: ResMut<Scheduler>,
     curr: Query<Entity, With<Initiative>>,
-    characters: Query<Entity, Or<(With<Player>, With<EnemyAI>)>>,
+    characters: Query<(Entity, &Speed, &StatusEffects)>,
     mut commands: Commands,
 ) {
     if let Ok(entity) = curr.single() {
         commands.entity(entity).remove::<Initiative>();
     }
 
-    for c in characters.iter() {
-        if !order.0.contains(&c) {
-            order.0.push_back(c);
-        }
+    for (c, _, _) in characters.iter() {
+        scheduler.add(c);
     }
 
-    if let Some(entity) = order.0.pop_front() {
+    let speed = |entity| {
+        characters
+            .get(entity)
+            .map_or(Speed::NORMAL, |(_, speed, statuses)| {
+                statuses.speed(speed.0)
+            })
+    };
+    if let Some(entity) = scheduler.next(speed) {
         commands.entity(entity).insert(Initiative);
-        order.0.push_back(entity);
     }
 }
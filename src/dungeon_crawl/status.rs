@@ -0,0 +1,207 @@
+use super::{Ev, GameRng, Health, Initiative};
+use crate::world_map::{GridPosition, TileFlags, WorldMap};
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StatusKind {
+    /// Takes `potency` damage every turn.
+    Poison,
+    /// Heals `potency` health every turn.
+    Regeneration,
+    /// Gains energy twice as fast.
+    Haste,
+    /// Gains energy half as fast.
+    Slow,
+    /// Stumbles in random directions, attacking whatever is there.
+    Confusion,
+    /// Can't see anything around it.
+    Blindness,
+    /// Skips its turns.
+    Paralysis,
+}
+
+impl StatusKind {
+    pub fn adjective(&self) -> &'static str {
+        match self {
+            StatusKind::Poison => "poisoned",
+            StatusKind::Regeneration => "regenerating",
+            StatusKind::Haste => "hasted",
+            StatusKind::Slow => "slowed",
+            StatusKind::Confusion => "confused",
+            StatusKind::Blindness => "blinded",
+            StatusKind::Paralysis => "paralyzed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Status {
+    pub kind: StatusKind,
+    /// Remaining turns of the affected entity.
+    pub duration: i32,
+    #[serde(default)]
+    pub potency: i32,
+}
+
+/// Active status effects of an entity, at most one per kind.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct StatusEffects(pub Vec<Status>);
+
+impl StatusEffects {
+    pub fn has(&self, kind: StatusKind) -> bool {
+        self.0.iter().any(|s| s.kind == kind)
+    }
+
+    /// The entity can't choose its own action.
+    pub fn skips_turn(&self) -> bool {
+        self.has(StatusKind::Paralysis) || self.has(StatusKind::Confusion)
+    }
+
+    pub fn speed(&self, speed: i32) -> i32 {
+        if self.has(StatusKind::Haste) {
+            speed * 2
+        } else if self.has(StatusKind::Slow) {
+            speed / 2
+        } else {
+            speed
+        }
+    }
+
+    pub fn view_radius(&self, radius: i32) -> i32 {
+        if self.has(StatusKind::Blindness) {
+            0
+        } else {
+            radius
+        }
+    }
+
+    /// Poison stacks its potency, other statuses only refresh the duration and
+    /// potency. Haste and slow cancel each other out, returns the cancelled status.
+    pub fn add(&mut self, status: Status) -> Option<StatusKind> {
+        let opposite = match status.kind {
+            StatusKind::Haste => Some(StatusKind::Slow),
+            StatusKind::Slow => Some(StatusKind::Haste),
+            _ => None,
+        };
+        if let Some(opposite) = opposite.filter(|o| self.has(*o)) {
+            self.remove(opposite);
+            return Some(opposite);
+        }
+
+        match self.0.iter_mut().find(|s| s.kind == status.kind) {
+            Some(current) => {
+                current.duration = i32::max(current.duration, status.duration);
+                current.potency = if status.kind == StatusKind::Poison {
+                    current.potency + status.potency
+                } else {
+                    i32::max(current.potency, status.potency)
+                };
+            }
+            None => self.0.push(status),
+        }
+        None
+    }
+
+    pub fn remove(&mut self, kind: StatusKind) {
+        self.0.retain(|s| s.kind != kind);
+    }
+
+    pub fn describe(&self) -> String {
+        self.0
+            .iter()
+            .map(|s| format!("{} {}", s.kind.adjective(), s.duration))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Chooses the action of an entity that can't choose it itself.
+pub fn forced_action(
+    current: Query<(Entity, &GridPosition, &StatusEffects), With<Initiative>>,
+    healthy: Query<(), With<Health>>,
+    world: Res<WorldMap>,
+    mut rng: ResMut<GameRng>,
+    mut evs: EventWriter<Ev>,
+) {
+    let (entity, position, statuses) = match current.single() {
+        Ok(current) => current,
+        Err(_) => return,
+    };
+
+    if statuses.has(StatusKind::Paralysis) {
+        evs.send(Ev::Nothing);
+    } else if statuses.has(StatusKind::Confusion) {
+        let neighbours = world.neighbours(*position);
+        if neighbours.is_empty() {
+            evs.send(Ev::Nothing);
+            return;
+        }
+        let new_pos = neighbours[rng.0.gen_range(0..neighbours.len())];
+
+        let target = world.entities[new_pos]
+            .iter()
+            .find(|e| healthy.get(**e).is_ok());
+        if let Some(&target) = target {
+            evs.send(Ev::Attack(entity, target));
+        } else if !world.tiles[new_pos].contains(TileFlags::BLOCKS_MOVEMENT) {
+            evs.send(Ev::Move(entity, *position, new_pos));
+        } else {
+            evs.send(Ev::Nothing);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(kind: StatusKind, duration: i32, potency: i32) -> Status {
+        Status {
+            kind,
+            duration,
+            potency,
+        }
+    }
+
+    #[test]
+    fn poison_stacks_its_potency() {
+        let mut statuses = StatusEffects::default();
+        statuses.add(status(StatusKind::Poison, 5, 2));
+        statuses.add(status(StatusKind::Poison, 3, 1));
+        assert_eq!(statuses.0, vec![status(StatusKind::Poison, 5, 3)]);
+    }
+
+    #[test]
+    fn other_statuses_refresh_duration_and_potency() {
+        let mut statuses = StatusEffects::default();
+        statuses.add(status(StatusKind::Regeneration, 3, 1));
+        statuses.add(status(StatusKind::Regeneration, 8, 2));
+        statuses.add(status(StatusKind::Regeneration, 2, 1));
+        assert_eq!(statuses.0, vec![status(StatusKind::Regeneration, 8, 2)]);
+    }
+
+    #[test]
+    fn haste_and_slow_cancel_each_other_out() {
+        let mut statuses = StatusEffects::default();
+        assert_eq!(statuses.add(status(StatusKind::Haste, 10, 0)), None);
+        assert_eq!(
+            statuses.add(status(StatusKind::Slow, 10, 0)),
+            Some(StatusKind::Haste)
+        );
+        assert!(statuses.0.is_empty());
+        assert_eq!(statuses.speed(100), 100);
+    }
+
+    #[test]
+    fn statuses_change_speed_and_sight() {
+        let mut statuses = StatusEffects::default();
+        statuses.add(status(StatusKind::Slow, 10, 0));
+        statuses.add(status(StatusKind::Blindness, 10, 0));
+        assert_eq!(statuses.speed(100), 50);
+        assert_eq!(statuses.view_radius(8), 0);
+        statuses.remove(StatusKind::Blindness);
+        assert_eq!(statuses.view_radius(8), 8);
+    }
+}
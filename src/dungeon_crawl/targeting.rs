@@ -0,0 +1,201 @@
+use super::{Cursor, Ev, GameData, Health, LogCategory, LogMessage, Player};
+use crate::{
+    controls::{Action, ActionInput},
+    items::{ItemTemplate, ItemTemplates, Slot, Targeting},
+    world_map::{GridPosition, TileFlags, WorldMap},
+    AppState,
+};
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+// The cursor has a position too, and it moves while aiming.
+type PlayerQuery<'a> = Query<'a, (Entity, &'static GridPosition), (With<Player>, Without<Cursor>)>;
+type CreatureQuery<'a> =
+    Query<'a, &'static GridPosition, (With<Health>, Without<Player>, Without<Cursor>)>;
+
+/// Where the player can aim the selected item.
+#[derive(SystemParam)]
+pub struct TargetCandidates<'a> {
+    world: Res<'a, WorldMap>,
+    items: ItemTemplates<'a>,
+    player: PlayerQuery<'a>,
+    creatures: CreatureQuery<'a>,
+    healthy: Query<'a, (), With<Health>>,
+}
+
+impl<'a> TargetCandidates<'a> {
+    pub fn player(&self) -> (Entity, GridPosition) {
+        let (entity, position) = self.player.single().unwrap();
+        (entity, *position)
+    }
+
+    /// Visible creatures other than the player, closest first.
+    fn visible(&self) -> Vec<GridPosition> {
+        let (_, center) = self.player();
+        let mut visible: Vec<GridPosition> = self
+            .creatures
+            .iter()
+            .copied()
+            .filter(|pos| self.world.tiles[*pos].contains(TileFlags::IN_VIEW))
+            .collect();
+        visible.sort_by_key(|pos| {
+            let distance = (pos.x - center.x).pow(2) + (pos.y - center.y).pow(2);
+            (distance, pos.x, pos.y)
+        });
+        visible
+    }
+
+    /// The player for equipment and helpful items, otherwise the closest creature.
+    pub fn nearest(&self, template: &ItemTemplate) -> GridPosition {
+        let (_, player) = self.player();
+        if template.targeting == Targeting::OnSelf
+            || template.slot.is_some()
+            || template.is_beneficial()
+        {
+            player
+        } else {
+            self.visible().first().copied().unwrap_or(player)
+        }
+    }
+
+    /// The visible creature after `current`, wrapping around.
+    pub fn next(&self, current: GridPosition) -> GridPosition {
+        let visible = self.visible();
+        let next = visible
+            .iter()
+            .position(|pos| *pos == current)
+            .map_or(0, |i| (i + 1) % visible.len());
+        visible.get(next).copied().unwrap_or(current)
+    }
+}
+
+pub fn snap_cursor(
+    data: Res<GameData>,
+    candidates: TargetCandidates,
+    mut cursor: Query<&mut GridPosition, With<Cursor>>,
+) {
+    let item = data.selected.and_then(|i| data.inventory[i]);
+    if let (Some(item), Ok(mut cursor)) = (item, cursor.single_mut()) {
+        *cursor = candidates.nearest(candidates.items.get(item));
+    }
+}
+
+/// Aiming the selected item with the keyboard or the mouse. Confirm uses it on the
+/// cursor, throw throws it there and cancel puts it back.
+pub fn targeting_control(
+    mut input: ActionInput,
+    mut data: ResMut<GameData>,
+    candidates: TargetCandidates,
+    mut cursor: Query<&mut GridPosition, With<Cursor>>,
+    mut app_state: ResMut<State<AppState>>,
+    mut evs: EventWriter<Ev>,
+    mut log: EventWriter<LogMessage>,
+) {
+    let selected = data
+        .selected
+        .and_then(|index| data.inventory[index].map(|item| (index, item)));
+    let ((index, item), mut cursor) = match (selected, cursor.single_mut()) {
+        (Some(selected), Ok(cursor)) if !input.just_pressed(Action::Cancel) => (selected, cursor),
+        _ => {
+            // Cancel would also quit the game once it's resumed in this frame.
+            input.reset(Action::Cancel);
+            data.selected = None;
+            app_state.pop().unwrap();
+            return;
+        }
+    };
+
+    let confirm = input.just_pressed(Action::Confirm);
+    let throw = input.just_pressed(Action::Throw);
+    match input.just_pressed_action() {
+        Some(Action::NextTarget) => *cursor = candidates.next(*cursor),
+        Some(Action::UseSlot(index)) => {
+            if let Some(&Some(item)) = data.inventory.get(index) {
+                data.selected = Some(index);
+                *cursor = candidates.nearest(candidates.items.get(item));
+            }
+            return;
+        }
+        Some(action) => {
+            if let Some(step) = action.direction() {
+                cursor.x += step.x;
+                cursor.y += step.y;
+            }
+        }
+        None => {}
+    }
+
+    let (player, position) = candidates.player();
+    let target = *cursor;
+    if !candidates.world.tiles[target].contains(TileFlags::IN_VIEW) {
+        return;
+    }
+
+    let used = if confirm {
+        let template = candidates.items.get(item);
+        let on_player = candidates.world.entities[target].contains(&player);
+        let has_target = match template.targeting {
+            Targeting::OnSelf => on_player,
+            Targeting::Single => candidates.world.entities[target]
+                .iter()
+                .any(|e| candidates.healthy.get(*e).is_ok()),
+            Targeting::Area(_) => true,
+        };
+
+        // Equipment goes on the player wherever the cursor is.
+        match template.slot {
+            Some(Slot::Weapon) => {
+                data.weapon = Some(item);
+                true
+            }
+            Some(Slot::Armor) => {
+                data.armor = Some(item);
+                true
+            }
+            None if !template.in_range(position, target) => {
+                log.send(LogMessage(
+                    LogCategory::System,
+                    "That's out of range.".into(),
+                ));
+                false
+            }
+            None if !candidates.world.line_of_sight(position, target) => {
+                log.send(LogMessage(
+                    LogCategory::System,
+                    "Something is in the way.".into(),
+                ));
+                false
+            }
+            None if has_target => {
+                evs.send(Ev::UseItem(player, item, target));
+                data.inventory[index] = None;
+                true
+            }
+            None => {
+                let reason = match template.targeting {
+                    Targeting::OnSelf => "You can only use that on yourself.",
+                    _ => "There's nothing to use that on.",
+                };
+                log.send(LogMessage(LogCategory::System, reason.into()));
+                false
+            }
+        }
+    } else if throw {
+        evs.send(Ev::DropItem(player, item, target));
+        if data.armor == Some(item) {
+            data.armor = None;
+        } else if data.weapon == Some(item) {
+            data.weapon = None;
+        }
+        data.inventory[index] = None;
+        true
+    } else {
+        false
+    };
+
+    if used {
+        // Left click also travels once the game is back in this frame.
+        input.reset_all();
+        data.selected = None;
+        app_state.pop().unwrap();
+    }
+}
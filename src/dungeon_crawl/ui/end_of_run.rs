@@ -0,0 +1,117 @@
+use crate::{
+    dungeon_crawl::{GameData, RunOutcome},
+    AppState,
+};
+use bevy::prelude::*;
+
+pub struct EndOfRunCanvas;
+pub struct EndOfRunButton;
+
+pub fn create(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    data: Res<GameData>,
+) {
+    let title = match data.outcome {
+        Some(RunOutcome::Won { .. }) => "VICTORY",
+        _ => "YOU DIED",
+    };
+    let summary = format!(
+        "{}\n\nFloor reached: {}\nLevel: {}\nKills: {}",
+        data.outcome
+            .as_ref()
+            .map_or(String::new(), |o| o.to_string()),
+        data.floor,
+        data.level,
+        data.kills.values().sum::<u32>()
+    );
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            material: materials.add(Color::hex("101010").unwrap().into()),
+            ..Default::default()
+        })
+        .insert(EndOfRunCanvas)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    margin: Rect::all(Val::Px(50.0)),
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    title,
+                    TextStyle {
+                        font: asset_server.load("Roboto/Roboto-Regular.ttf"),
+                        font_size: 100.0,
+                        color: Color::hex("826007").unwrap(),
+                    },
+                    TextAlignment::default(),
+                ),
+                ..Default::default()
+            });
+
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    summary,
+                    TextStyle {
+                        font: asset_server.load("Roboto/Roboto-Regular.ttf"),
+                        font_size: 40.0,
+                        color: Color::WHITE,
+                    },
+                    TextAlignment {
+                        horizontal: HorizontalAlign::Center,
+                        ..Default::default()
+                    },
+                ),
+                ..Default::default()
+            });
+
+            parent
+                .spawn_bundle(ButtonBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(50.0)),
+                        ..Default::default()
+                    },
+                    material: materials.add(Color::hex("101010").unwrap().into()),
+                    ..Default::default()
+                })
+                .insert(EndOfRunButton)
+                .with_children(|parent| {
+                    parent.spawn_bundle(TextBundle {
+                        text: Text::with_section(
+                            "MAIN MENU",
+                            TextStyle {
+                                font: asset_server.load("Roboto/Roboto-Regular.ttf"),
+                                font_size: 100.0,
+                                color: Color::WHITE,
+                            },
+                            TextAlignment::default(),
+                        ),
+                        ..Default::default()
+                    });
+                });
+        });
+}
+
+pub fn interaction(
+    q: Query<&Interaction, With<EndOfRunButton>>,
+    keys: Res<Input<KeyCode>>,
+    mut app_state: ResMut<State<AppState>>,
+) {
+    let clicked = q.iter().any(|i| *i == Interaction::Clicked);
+    if clicked || keys.just_pressed(KeyCode::Return) || keys.just_pressed(KeyCode::Escape) {
+        app_state.set(AppState::MainMenu).unwrap();
+    }
+}
+
+pub fn cleanup(mut commands: Commands, q: Query<Entity, With<EndOfRunCanvas>>) {
+    commands.entity(q.single().unwrap()).despawn_recursive();
+}
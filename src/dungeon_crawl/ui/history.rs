@@ -0,0 +1,150 @@
+use super::Logs;
+use crate::{
+    controls::{Action, ActionInput, Controls},
+    AppState,
+};
+use bevy::{input::mouse::MouseWheel, prelude::*};
+
+pub struct HistoryCanvas;
+pub struct HistoryText;
+/// How many of the newest messages are scrolled past.
+#[derive(Default)]
+pub struct HistoryScroll(usize);
+
+const LINES: usize = 30;
+
+pub fn open(mut input: ActionInput, mut app_state: ResMut<State<AppState>>) {
+    if input.just_pressed(Action::Messages) {
+        // The history runs in the same frame and would close on the same press.
+        input.reset(Action::Messages);
+        // Not when the turn already changed the state, e.g. the run ended.
+        let _ = app_state.push(AppState::MessageHistory);
+    }
+}
+
+pub fn create(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut scroll: ResMut<HistoryScroll>,
+    controls: Res<Controls>,
+) {
+    scroll.0 = 0;
+    let help = format!(
+        "MESSAGES ({} / {} or mouse wheel to scroll, {} / {} for a page, {} or {} to close)",
+        controls.describe(Action::MoveNorth),
+        controls.describe(Action::MoveSouth),
+        controls.describe(Action::PageUp),
+        controls.describe(Action::PageDown),
+        controls.describe(Action::Messages),
+        controls.describe(Action::Cancel)
+    );
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                align_items: AlignItems::FlexStart,
+                justify_content: JustifyContent::FlexStart,
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: Rect::all(Val::Px(30.0)),
+                ..Default::default()
+            },
+            material: materials.add(Color::hex("101010").unwrap().into()),
+            ..Default::default()
+        })
+        .insert(HistoryCanvas)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    help,
+                    TextStyle {
+                        font: asset_server.load("Roboto/Roboto-Regular.ttf"),
+                        font_size: 30.0,
+                        color: Color::hex("826007").unwrap(),
+                    },
+                    TextAlignment::default(),
+                ),
+                ..Default::default()
+            });
+
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        margin: Rect {
+                            top: Val::Px(20.0),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    text: Text::default(),
+                    ..Default::default()
+                })
+                .insert(HistoryText);
+        });
+}
+
+pub fn update(
+    mut input: ActionInput,
+    mut wheel: EventReader<MouseWheel>,
+    mut scroll: ResMut<HistoryScroll>,
+    logs: Res<Logs>,
+    asset_server: Res<AssetServer>,
+    mut text: Query<(&mut Text, ChangeTrackers<HistoryText>)>,
+    mut app_state: ResMut<State<AppState>>,
+) {
+    for action in [Action::Messages, Action::Cancel] {
+        if input.just_pressed(action) {
+            // Cancel would also quit the game once it's resumed in this frame.
+            input.reset(action);
+            app_state.pop().unwrap();
+            return;
+        }
+    }
+
+    // Scrolling up shows older messages.
+    let mut offset = scroll.0 as i32;
+    if input.just_pressed(Action::MoveNorth) {
+        offset += 1;
+    }
+    if input.just_pressed(Action::MoveSouth) {
+        offset -= 1;
+    }
+    if input.just_pressed(Action::PageUp) {
+        offset += LINES as i32;
+    }
+    if input.just_pressed(Action::PageDown) {
+        offset -= LINES as i32;
+    }
+    for event in wheel.iter() {
+        offset += 3 * event.y.signum() as i32;
+    }
+    let max = logs.0.len().saturating_sub(LINES) as i32;
+    let offset = offset.clamp(0, max) as usize;
+    if scroll.0 != offset {
+        scroll.0 = offset;
+    }
+
+    let (mut text, history) = match text.single_mut() {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+    // Every line is rebuilt, so only when there's something new to show.
+    if !history.is_added() && !logs.is_changed() && !scroll.is_changed() {
+        return;
+    }
+
+    let style = TextStyle {
+        font: asset_server.load("Roboto/Roboto-Regular.ttf"),
+        font_size: 25.0,
+        color: Color::WHITE,
+    };
+    let shown: Vec<_> = logs.0.iter().skip(scroll.0).take(LINES).collect();
+    // Oldest message at the top
+    text.sections = Logs::sections(shown.into_iter().rev(), &style);
+}
+
+pub fn cleanup(mut commands: Commands, q: Query<Entity, With<HistoryCanvas>>) {
+    commands.entity(q.single().unwrap()).despawn_recursive();
+}
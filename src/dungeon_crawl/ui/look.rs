@@ -0,0 +1,315 @@
+use crate::{
+    controls::{Action, ActionInput, Controls},
+    dungeon_crawl::{CombatStats, Cursor, GameData, Health, Item, Name, Player, StatusEffects},
+    items::ItemTemplates,
+    monsters::MonsterTable,
+    world_map::{GridPosition, Tile, TileFlags, WorldMap},
+    AppState,
+};
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+pub struct LookCanvas;
+pub struct LookText;
+
+/// Everything needed to describe an entity to the player.
+#[derive(SystemParam)]
+pub struct Examine<'a> {
+    names: Query<'a, &'static Name>,
+    health: Query<'a, &'static Health>,
+    statuses: Query<'a, &'static StatusEffects>,
+    stats: Query<'a, &'static CombatStats>,
+    player: Query<'a, (), With<Player>>,
+    tiles: Query<'a, (), With<Tile>>,
+    item_entities: Query<'a, (), With<Item>>,
+    items: ItemTemplates<'a>,
+    monsters: Res<'a, MonsterTable>,
+    data: Res<'a, GameData>,
+}
+
+impl<'a> Examine<'a> {
+    /// One line with the name, health and statuses.
+    pub fn summary(&self, entity: Entity) -> String {
+        let name = self.names.get(entity).unwrap().capitalized();
+        let health = self
+            .health
+            .get(entity)
+            .map_or(String::from(""), |h| format!(" ({}/{})", h.current, h.max));
+        let statuses = self
+            .statuses
+            .get(entity)
+            .ok()
+            .filter(|s| !s.0.is_empty())
+            .map_or(String::from(""), |s| format!(" [{}]", s.describe()));
+        format!("{}{}{}", name, health, statuses)
+    }
+
+    /// Everything known about the entity, one line per fact.
+    pub fn describe(&self, entity: Entity) -> Vec<String> {
+        let name = self.names.get(entity).unwrap();
+        let mut lines = vec![name.capitalized()];
+
+        if let Ok(health) = self.health.get(entity) {
+            lines.push(format!("HP: {}/{}", health.current, health.max));
+        }
+
+        let is_player = self.player.get(entity).is_ok();
+        if let Ok(stats) = self.stats.get(entity) {
+            let stats = if is_player {
+                *stats + self.items.equipment(&self.data)
+            } else {
+                *stats
+            };
+            lines.push(format!(
+                "Power {}, defense {}, accuracy {}, evasion {}",
+                stats.power, stats.defense, stats.accuracy, stats.evasion
+            ));
+        }
+
+        if is_player {
+            let name_of = |item: Option<Entity>| {
+                item.map_or(String::from("nothing"), |item| {
+                    self.names.get(item).unwrap().0.clone()
+                })
+            };
+            lines.push(format!("Weapon: {}", name_of(self.data.weapon)));
+            lines.push(format!("Armor: {}", name_of(self.data.armor)));
+        }
+
+        if let Ok(statuses) = self.statuses.get(entity) {
+            if !statuses.0.is_empty() {
+                lines.push(format!("Status: {}", statuses.describe()));
+            }
+        }
+
+        let description = if is_player {
+            "That's you."
+        } else if self.item_entities.get(entity).is_ok() {
+            let template = self.items.get(entity);
+            if template.slot.is_some() {
+                lines.push(format!("When equipped: {}", bonus(&template.stats)));
+            }
+            &template.description
+        } else {
+            self.monsters
+                .get(&name.0)
+                .map_or("", |m| m.description.as_str())
+        };
+        if !description.is_empty() {
+            lines.push(String::from(description));
+        }
+
+        lines
+    }
+
+    /// Descriptions of what is on the tile, creatures and items before the tile itself.
+    pub fn describe_tile(&self, entities: &[Entity]) -> Vec<String> {
+        let (tiles, others): (Vec<Entity>, Vec<Entity>) =
+            entities.iter().partition(|e| self.tiles.get(**e).is_ok());
+        let shown = if others.is_empty() { tiles } else { others };
+
+        shown
+            .into_iter()
+            .map(|e| self.describe(e))
+            .intersperse(vec![String::new()])
+            .flatten()
+            .collect()
+    }
+}
+
+/// Non-zero stats with their sign, e.g. "+1 power, -1 evasion".
+fn bonus(stats: &CombatStats) -> String {
+    let bonuses: Vec<String> = [
+        (stats.power, "power"),
+        (stats.defense, "defense"),
+        (stats.accuracy, "accuracy"),
+        (stats.evasion, "evasion"),
+    ]
+    .iter()
+    .filter(|(value, _)| *value != 0)
+    .map(|(value, name)| format!("{:+} {}", value, name))
+    .collect();
+    bonuses.join(", ")
+}
+
+pub fn open(mut input: ActionInput, mut app_state: ResMut<State<AppState>>) {
+    if input.just_pressed(Action::Look) {
+        // Look mode runs in the same frame and would close on the same press.
+        input.reset(Action::Look);
+        // Not when the turn already changed the state, e.g. the run ended.
+        let _ = app_state.push(AppState::Look);
+    }
+}
+
+pub fn create(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    controls: Res<Controls>,
+    player: Query<&GridPosition, (With<Player>, Without<Cursor>)>,
+    mut cursor: Query<(&mut GridPosition, &mut Visible), With<Cursor>>,
+) {
+    let (mut position, mut visible) = cursor.single_mut().unwrap();
+    if let Ok(player) = player.single() {
+        *position = *player;
+    }
+    visible.is_visible = true;
+
+    let help = format!(
+        "LOOK (movement keys to move, {} for next, {} or {} to close)",
+        controls.describe(Action::NextTarget),
+        controls.describe(Action::Look),
+        controls.describe(Action::Cancel)
+    );
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Px(400.0), Val::Auto),
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(0.0),
+                    top: Val::Px(0.0),
+                    ..Default::default()
+                },
+                padding: Rect::all(Val::Px(15.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            material: materials.add(Color::hex("101010").unwrap().into()),
+            ..Default::default()
+        })
+        .insert(LookCanvas)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    help,
+                    TextStyle {
+                        font: asset_server.load("Roboto/Roboto-Regular.ttf"),
+                        font_size: 20.0,
+                        color: Color::hex("826007").unwrap(),
+                    },
+                    TextAlignment::default(),
+                ),
+                style: Style {
+                    max_size: Size::new(Val::Px(370.0), Val::Undefined),
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font: asset_server.load("Roboto/Roboto-Regular.ttf"),
+                            font_size: 20.0,
+                            color: Color::WHITE,
+                        },
+                        TextAlignment::default(),
+                    ),
+                    style: Style {
+                        max_size: Size::new(Val::Px(370.0), Val::Undefined),
+                        margin: Rect {
+                            top: Val::Px(10.0),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(LookText);
+        });
+}
+
+// Creatures and items, but not the player or the cursor on top of them.
+type TargetQuery<'a> = Query<
+    'a,
+    &'static GridPosition,
+    (
+        Or<(With<Health>, With<Item>)>,
+        Without<Cursor>,
+        Without<Player>,
+    ),
+>;
+
+/// What the cursor can jump to.
+#[derive(SystemParam)]
+pub struct LookTargets<'a> {
+    world: Res<'a, WorldMap>,
+    player: Query<'a, &'static GridPosition, (With<Player>, Without<Cursor>)>,
+    targets: TargetQuery<'a>,
+}
+
+impl<'a> LookTargets<'a> {
+    /// The visible creature or item after `current`, from the closest to the player.
+    fn next(&self, current: GridPosition) -> Option<GridPosition> {
+        let center = self.player.single().map_or(current, |p| *p);
+        let mut visible: Vec<GridPosition> = self
+            .targets
+            .iter()
+            .copied()
+            .filter(|pos| self.world.tiles[*pos].contains(TileFlags::IN_VIEW))
+            .collect();
+        visible.sort_by_key(|pos| {
+            let distance = (pos.x - center.x).pow(2) + (pos.y - center.y).pow(2);
+            (distance, pos.x, pos.y)
+        });
+        visible.dedup();
+
+        let next = visible
+            .iter()
+            .position(|pos| *pos == current)
+            .map_or(0, |i| (i + 1) % visible.len());
+        visible.get(next).copied()
+    }
+}
+
+pub fn update(
+    mut input: ActionInput,
+    mut app_state: ResMut<State<AppState>>,
+    targets: LookTargets,
+    mut cursor: Query<&mut GridPosition, With<Cursor>>,
+    examine: Examine,
+    mut text: Query<&mut Text, With<LookText>>,
+) {
+    for action in [Action::Look, Action::Cancel] {
+        if input.just_pressed(action) {
+            // Cancel would also quit the game once it's resumed in this frame.
+            input.reset(action);
+            app_state.pop().unwrap();
+            return;
+        }
+    }
+
+    let mut cursor = cursor.single_mut().unwrap();
+    match input.just_pressed_action() {
+        Some(Action::NextTarget) => {
+            if let Some(pos) = targets.next(*cursor) {
+                *cursor = pos;
+            }
+        }
+        Some(action) => {
+            if let Some(step) = action.direction() {
+                cursor.x += step.x;
+                cursor.y += step.y;
+            }
+        }
+        None => {}
+    }
+
+    let world = &targets.world;
+    let details = match world.tiles.get(cursor.x, cursor.y) {
+        Some(tile) if tile.contains(TileFlags::IN_VIEW) => {
+            examine.describe_tile(&world.entities[*cursor])
+        }
+        _ => vec![String::from("You can't see there.")],
+    };
+    if let Ok(mut text) = text.single_mut() {
+        text.sections[0].value = details.join("\n");
+    }
+}
+
+pub fn cleanup(mut commands: Commands, q: Query<Entity, With<LookCanvas>>) {
+    commands.entity(q.single().unwrap()).despawn_recursive();
+}
@@ -1,7 +1,16 @@
+mod end_of_run;
+mod history;
+mod look;
+mod targeting;
+mod travel;
 mod ui_setup;
 
-use super::{Cursor, GameData, Health, Name, Player, TurnState};
+use super::{
+    log::{LogCategory, LogEntry, Logs},
+    Cursor, GameData, Health, Name, Player, TurnState,
+};
 use crate::{
+    controls::{Action, Controls},
     world_map::{Grid, GridPosition, TileFlags, WorldMap},
     AppState, UiCamera,
 };
@@ -10,7 +19,6 @@ use bevy::{
     prelude::*,
     render::camera::{Camera, OrthographicProjection},
 };
-use std::collections::VecDeque;
 
 pub struct MyCanvas;
 pub struct MyHpText;
@@ -22,17 +30,52 @@ pub struct MyLog;
 pub struct MyDetails;
 pub struct MyInventory;
 
-pub struct LogMessage(pub String);
-#[derive(Default)]
-pub struct Logs(VecDeque<String>);
+impl LogCategory {
+    pub fn color(&self) -> Color {
+        match self {
+            LogCategory::Combat => Color::hex("DA0037").unwrap(),
+            LogCategory::Loot => Color::hex("826007").unwrap(),
+            LogCategory::Status => Color::hex("43AD39").unwrap(),
+            LogCategory::System => Color::hex("EDEDED").unwrap(),
+        }
+    }
+}
+
+impl Logs {
+    /// Colored text sections for the given entries, one line each.
+    pub fn sections<'a>(
+        entries: impl Iterator<Item = &'a LogEntry>,
+        style: &TextStyle,
+    ) -> Vec<TextSection> {
+        entries
+            .map(|entry| TextSection {
+                value: format!("[{}] {}\n", entry.turn, entry.text),
+                style: TextStyle {
+                    color: entry.category.color(),
+                    ..style.clone()
+                },
+            })
+            .collect()
+    }
+}
 
 pub struct DungeonCrawlUIPlugin;
 impl Plugin for DungeonCrawlUIPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_event::<LogMessage>().init_resource::<Logs>();
+        app.init_resource::<history::HistoryScroll>();
         app.add_system_set(
-            SystemSet::on_enter(AppState::DungeonCrawlEnter).with_system(ui_setup::create.system()),
+            SystemSet::on_enter(AppState::DungeonCrawlEnter)
+                .with_system(ui_setup::create.system())
+                .with_system(travel::create.system()),
         );
+        for state in [
+            AppState::DungeonCrawlExitToMenu,
+            AppState::EndOfRun,
+            AppState::DungeonCrawlDescend,
+            AppState::DungeonCrawlAscend,
+        ] {
+            app.add_system_set(SystemSet::on_enter(state).with_system(ui_setup::cleanup.system()));
+        }
         app.add_system_set(
             SystemSet::on_update(AppState::DungeonCrawl(TurnState::Turn))
                 .with_system(update_position.system().label("positions"))
@@ -43,8 +86,59 @@ impl Plugin for DungeonCrawlUIPlugin {
                 .with_system(update_log.system())
                 .with_system(update_cursor.system().before("positions"))
                 .with_system(update_details.system())
+                .with_system(update_inventory.system())
+                .with_system(travel::update_path.system().after("positions"))
+                // After the events, so a state they change to comes first.
+                .with_system(history::open.system().after("evs"))
+                .with_system(look::open.system().after("evs")),
+        );
+        app.add_system_set(
+            SystemSet::on_exit(AppState::DungeonCrawl(TurnState::Turn))
+                .with_system(travel::cleanup.system()),
+        );
+        app.add_system_set(
+            SystemSet::on_pause(AppState::DungeonCrawl(TurnState::Turn))
+                .with_system(travel::cleanup.system()),
+        );
+        app.add_system_set(SystemSet::on_enter(AppState::Look).with_system(look::create.system()));
+        app.add_system_set(
+            SystemSet::on_update(AppState::Look)
+                .with_system(look::update.system().label("look"))
+                .with_system(update_position.system().after("look")),
+        );
+        app.add_system_set(SystemSet::on_exit(AppState::Look).with_system(look::cleanup.system()));
+        app.add_system_set(
+            SystemSet::on_enter(AppState::Targeting).with_system(targeting::create.system()),
+        );
+        app.add_system_set(
+            SystemSet::on_update(AppState::Targeting)
+                .with_system(targeting::follow_mouse.system().before("targeting"))
+                .with_system(targeting::update_highlight.system().after("targeting"))
+                .with_system(update_position.system().after("targeting"))
+                .with_system(update_details.system().after("targeting"))
                 .with_system(update_inventory.system()),
         );
+        app.add_system_set(
+            SystemSet::on_exit(AppState::Targeting).with_system(targeting::cleanup.system()),
+        );
+        app.add_system_set(
+            SystemSet::on_enter(AppState::MessageHistory).with_system(history::create.system()),
+        );
+        app.add_system_set(
+            SystemSet::on_update(AppState::MessageHistory).with_system(history::update.system()),
+        );
+        app.add_system_set(
+            SystemSet::on_exit(AppState::MessageHistory).with_system(history::cleanup.system()),
+        );
+        app.add_system_set(
+            SystemSet::on_enter(AppState::EndOfRun).with_system(end_of_run::create.system()),
+        );
+        app.add_system_set(
+            SystemSet::on_update(AppState::EndOfRun).with_system(end_of_run::interaction.system()),
+        );
+        app.add_system_set(
+            SystemSet::on_exit(AppState::EndOfRun).with_system(end_of_run::cleanup.system()),
+        );
     }
 }
 
@@ -102,57 +196,73 @@ pub fn update_xp(
 }
 
 pub fn update_floor(mut text: Query<&mut Text, With<MyFloorText>>, data: Res<GameData>) {
-    text.single_mut().unwrap().sections[0].value = format!("Floor {}", data.floor);
+    text.single_mut().unwrap().sections[0].value =
+        format!("Floor {}\nSeed: {}", data.floor, data.seed);
 }
 
-pub fn update_log(
-    mut text: Query<&mut Text, With<MyLog>>,
-    mut messages: EventReader<LogMessage>,
-    mut log: ResMut<Logs>,
-) {
-    for m in messages.iter() {
-        log.0.push_front(m.0.clone());
-    }
-    log.0.resize(6, String::from(" "));
-
-    text.single_mut().unwrap().sections[0].value = log
-        .0
-        .iter()
-        .intersperse(&String::from("\n"))
-        .cloned()
-        .collect();
+pub fn update_log(mut text: Query<&mut Text, With<MyLog>>, log: Res<Logs>) {
+    let mut text = text.single_mut().unwrap();
+    let style = text.sections[0].style.clone();
+
+    let mut sections = Logs::sections(log.0.iter().take(6), &style);
+    // Keep the height of six lines
+    sections.resize(
+        6,
+        TextSection {
+            value: String::from(" \n"),
+            style,
+        },
+    );
+    sections[5].value.pop();
+    text.sections = sections;
+}
+
+/// The camera looking at the map rather than the UI.
+pub type MapCamera<'a> = Query<
+    'a,
+    (&'static Transform, &'static OrthographicProjection),
+    (With<Camera>, Without<UiCamera>),
+>;
+
+/// Grid cell under the mouse, if it's over the window.
+pub fn mouse_position(
+    window: &Window,
+    (camera, orto): (&Transform, &OrthographicProjection),
+    grid: &Grid,
+) -> Option<GridPosition> {
+    let pos = window.cursor_position()?;
+    let size = Vec2::new(window.width(), window.height());
+    let pos = (pos - size / 2.0) * orto.scale;
+    let world_pos = camera.compute_matrix() * pos.extend(0.0).extend(1.0);
+    let grid_pos =
+        (vec2(world_pos.x, world_pos.y) / grid.cell_size.as_f32() + vec2(0.5, 0.5)).as_i32();
+
+    Some(GridPosition {
+        x: grid_pos.x,
+        y: grid_pos.y,
+    })
 }
 
 pub fn update_cursor(
     windows: Res<Windows>,
-    camera: Query<(&Transform, &OrthographicProjection), (With<Camera>, Without<UiCamera>)>,
+    camera: MapCamera,
     grid: Res<Grid>,
-    inventory: Res<GameData>,
     mut cursor: Query<(&mut GridPosition, &mut Visible), With<Cursor>>,
 ) {
     let window = windows.get_primary().unwrap();
+    let (mut position, mut visible) = cursor.single_mut().unwrap();
 
-    if let Some(pos) = window.cursor_position() {
-        let size = Vec2::new(window.width(), window.height());
-        let (camera, orto) = camera.single().unwrap();
-        let pos = (pos - size / 2.0) * orto.scale;
-        let world_pos = camera.compute_matrix() * pos.extend(0.0).extend(1.0);
-        let grid_pos =
-            (vec2(world_pos.x, world_pos.y) / grid.cell_size.as_f32() + vec2(0.5, 0.5)).as_i32();
-
-        *cursor.single_mut().unwrap().0 = GridPosition {
-            x: grid_pos.x,
-            y: grid_pos.y,
-        };
+    if let Some(pos) = mouse_position(window, camera.single().unwrap(), &grid) {
+        *position = pos;
     }
 
-    cursor.single_mut().unwrap().1.is_visible = inventory.selected.is_some();
+    // Only shown while aiming.
+    visible.is_visible = false;
 }
 
 pub fn update_details(
     mut text: Query<&mut Text, With<MyDetails>>,
-    names: Query<&Name>,
-    health: Query<&Health>,
+    examine: look::Examine,
     world: Res<WorldMap>,
     cursor: Query<&GridPosition, With<Cursor>>,
 ) {
@@ -161,14 +271,8 @@ pub fn update_details(
     if let Some(tile) = world.tiles.get(grid_pos.x, grid_pos.y) {
         if tile.contains(TileFlags::IN_VIEW) {
             if let Some(entities) = world.entities.get(grid_pos.x, grid_pos.y) {
-                let mut details = vec![];
-                for entity in entities {
-                    let name = names.get(*entity).unwrap().capitalized();
-                    let health = health
-                        .get(*entity)
-                        .map_or(String::from(""), |h| format!(" ({}/{})", h.current, h.max));
-                    details.push(format!("{}{}", name, health));
-                }
+                let mut details: Vec<String> =
+                    entities.iter().map(|e| examine.summary(*e)).collect();
 
                 details.resize(4, String::from(" "));
                 text.single_mut().unwrap().sections[0].value = details
@@ -188,6 +292,7 @@ pub fn update_inventory(
     mut text: Query<&mut Text, With<MyInventory>>,
     inventory: Res<GameData>,
     names: Query<&Name>,
+    controls: Res<Controls>,
 ) {
     let ind = inventory.selected.unwrap_or(usize::MAX);
 
@@ -216,8 +321,10 @@ pub fn update_inventory(
     }
 
     if inventory.inventory.iter().all(|i| i.is_none()) {
-        text.single_mut().unwrap().sections[0].value =
-            String::from("Press G to pick up items\n \n \n \n ");
+        text.single_mut().unwrap().sections[0].value = format!(
+            "Press {} to pick up items\n \n \n \n ",
+            controls.describe(Action::PickUp)
+        );
     } else {
         text.single_mut().unwrap().sections[0].value =
             inv.into_iter().intersperse(String::from("\n")).collect();
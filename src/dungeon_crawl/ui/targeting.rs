@@ -0,0 +1,133 @@
+use super::{mouse_position, MapCamera};
+use crate::{
+    dungeon_crawl::{Cursor, GameData, Health, Player},
+    items::ItemTemplates,
+    world_map::{Grid, GridPosition, WorldMap},
+};
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+pub struct TargetHighlight;
+pub struct TargetHighlightMaterials {
+    /// Tiles the item would affect.
+    area: Handle<ColorMaterial>,
+    /// Affected tiles with a creature on them, the player included.
+    hit: Handle<ColorMaterial>,
+    /// The target is out of range or out of sight.
+    blocked: Handle<ColorMaterial>,
+}
+
+pub fn create(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut cursor: Query<&mut Visible, With<Cursor>>,
+) {
+    cursor.single_mut().unwrap().is_visible = true;
+
+    let mut tint = |hex: &str, alpha: f32| {
+        let mut color = Color::hex(hex).unwrap();
+        color.set_a(alpha);
+        materials.add(color.into())
+    };
+    commands.insert_resource(TargetHighlightMaterials {
+        area: tint("DA0037", 0.3),
+        hit: tint("DA0037", 0.6),
+        blocked: tint("EDEDED", 0.3),
+    });
+}
+
+/// The cursor only follows the mouse when it's used, so it doesn't fight with the keyboard.
+pub fn follow_mouse(
+    mut moved: EventReader<CursorMoved>,
+    buttons: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    camera: MapCamera,
+    grid: Res<Grid>,
+    mut cursor: Query<&mut GridPosition, With<Cursor>>,
+) {
+    let moved = moved.iter().count() > 0;
+    if !moved && buttons.get_just_pressed().next().is_none() {
+        return;
+    }
+
+    let window = windows.get_primary().unwrap();
+    if let Some(position) = mouse_position(window, camera.single().unwrap(), &grid) {
+        *cursor.single_mut().unwrap() = position;
+    }
+}
+
+/// The selected item, where it's aimed from and who is there to hit.
+#[derive(SystemParam)]
+pub struct Aim<'a> {
+    data: Res<'a, GameData>,
+    items: ItemTemplates<'a>,
+    world: Res<'a, WorldMap>,
+    player: Query<'a, &'static GridPosition, (With<Player>, Without<Cursor>)>,
+    healthy: Query<'a, (), With<Health>>,
+}
+
+/// Tints every tile the selected item would affect, so it's clear who will be hit.
+pub fn update_highlight(
+    mut commands: Commands,
+    aim: Aim,
+    grid: Res<Grid>,
+    materials: Res<TargetHighlightMaterials>,
+    cursor: Query<(&GridPosition, ChangeTrackers<GridPosition>), With<Cursor>>,
+    highlights: Query<Entity, With<TargetHighlight>>,
+) {
+    let Aim {
+        data,
+        items,
+        world,
+        player,
+        healthy,
+    } = aim;
+    let (target, tracker) = cursor.single().unwrap();
+    if !tracker.is_changed() && !data.is_changed() {
+        return;
+    }
+
+    for entity in highlights.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    let item = match data.selected.and_then(|index| data.inventory[index]) {
+        Some(item) => item,
+        None => return,
+    };
+    let template = items.get(item);
+    let position = match player.single() {
+        Ok(position) if template.slot.is_none() => *position,
+        _ => return,
+    };
+    if world.tiles.get(target.x, target.y).is_none() {
+        return;
+    }
+
+    let reachable = template.in_range(position, *target) && world.line_of_sight(position, *target);
+    let size = grid.cell_size.as_f32();
+    for tile in template.targeting.area(&world, *target) {
+        let material = if !reachable {
+            &materials.blocked
+        } else if world.entities[tile].iter().any(|e| healthy.get(*e).is_ok()) {
+            &materials.hit
+        } else {
+            &materials.area
+        };
+
+        commands
+            .spawn_bundle(SpriteBundle {
+                material: material.clone(),
+                sprite: Sprite::new(size),
+                transform: Transform::from_xyz(tile.x as f32 * size.x, tile.y as f32 * size.y, 4.0),
+                ..Default::default()
+            })
+            .insert(TargetHighlight);
+    }
+}
+
+pub fn cleanup(mut commands: Commands, highlights: Query<Entity, With<TargetHighlight>>) {
+    for entity in highlights.iter() {
+        commands.entity(entity).despawn();
+    }
+    commands.remove_resource::<TargetHighlightMaterials>();
+}
@@ -0,0 +1,81 @@
+use crate::{
+    dungeon_crawl::{activity::travel_path, Cursor, Player},
+    world_map::{Grid, GridPosition, WorldMap},
+};
+use bevy::prelude::*;
+
+pub struct TravelStep;
+/// The way the player would travel to the tile under the mouse.
+pub struct TravelPreview {
+    material: Handle<ColorMaterial>,
+    path: Vec<GridPosition>,
+    /// The player and cursor positions the path was found for.
+    from: Option<(GridPosition, GridPosition)>,
+}
+
+pub fn create(mut commands: Commands, mut materials: ResMut<Assets<ColorMaterial>>) {
+    let mut color = Color::hex("EDEDED").unwrap();
+    color.set_a(0.4);
+    commands.insert_resource(TravelPreview {
+        material: materials.add(color.into()),
+        path: vec![],
+        from: None,
+    });
+}
+
+/// Marks every step of the path travel would take, so it's clear where a click leads.
+pub fn update_path(
+    mut commands: Commands,
+    mut preview: ResMut<TravelPreview>,
+    world: Res<WorldMap>,
+    grid: Res<Grid>,
+    player: Query<&GridPosition, (With<Player>, Without<Cursor>)>,
+    cursor: Query<&GridPosition, With<Cursor>>,
+    steps: Query<Entity, With<TravelStep>>,
+) {
+    let from = match (player.single(), cursor.single()) {
+        (Ok(position), Ok(target)) => Some((*position, *target)),
+        _ => None,
+    };
+    if from == preview.from {
+        return;
+    }
+    preview.from = from;
+
+    let path = from
+        .and_then(|(position, target)| travel_path(&world, position, target))
+        .unwrap_or_default();
+    if path == preview.path {
+        return;
+    }
+
+    for entity in steps.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    let size = grid.cell_size.as_f32();
+    for tile in path.iter().skip(1) {
+        commands
+            .spawn_bundle(SpriteBundle {
+                material: preview.material.clone(),
+                sprite: Sprite::new(size / 3.0),
+                transform: Transform::from_xyz(tile.x as f32 * size.x, tile.y as f32 * size.y, 4.0),
+                ..Default::default()
+            })
+            .insert(TravelStep);
+    }
+    preview.path = path;
+}
+
+/// Hides the path until the player can travel again.
+pub fn cleanup(
+    mut commands: Commands,
+    mut preview: ResMut<TravelPreview>,
+    steps: Query<Entity, With<TravelStep>>,
+) {
+    for entity in steps.iter() {
+        commands.entity(entity).despawn();
+    }
+    preview.path.clear();
+    preview.from = None;
+}
@@ -123,7 +123,7 @@ pub fn create(
                         ..Default::default()
                     },
                     text: Text::with_section(
-                        "Floor 0",
+                        "Floor 0\nSeed: 0",
                         TextStyle {
                             font: asset_server.load("Roboto/Roboto-Regular.ttf"),
                             font_size: 20.0,
@@ -253,3 +253,9 @@ pub fn create(
                 .insert(MyInventory);
         });
 }
+
+pub fn cleanup(mut commands: Commands, q: Query<Entity, With<MyCanvas>>) {
+    for e in q.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
@@ -0,0 +1,442 @@
+use crate::{
+    controls::Controls,
+    dungeon_crawl::{
+        DungeonCrawlPlugin, Ev, GameRng, Health, Initiative, LogMessage, Player, StatusEffects,
+        TurnState,
+    },
+    world_generation::WorldGenerationPlugins,
+    world_map::{GridPosition, TileFlags, WorldMap},
+    AppState,
+};
+use bevy::{
+    app::{Events, ManualEventReader},
+    input::InputPlugin,
+    prelude::*,
+};
+use rand::Rng;
+
+/// Builds an app with only the game logic: no window, rendering, asset loading, UI or
+/// files written for the player, like the saved game and high scores.
+/// It starts by generating the first floor. Turns can be driven by sending `Ev`s
+/// with `send_ev` and calling `App::update`.
+pub fn headless_app() -> AppBuilder {
+    let mut app = App::build();
+    app.add_plugins(MinimalPlugins)
+        // Player control still reads the input resources.
+        .add_plugin(InputPlugin)
+        .init_resource::<Controls>()
+        .add_state(AppState::WorldGeneration)
+        .add_plugin(DungeonCrawlPlugin)
+        .add_plugins(WorldGenerationPlugins);
+    app
+}
+
+pub fn send_ev(app: &mut App, ev: Ev) {
+    app.world.get_resource_mut::<Events<Ev>>().unwrap().send(ev);
+}
+
+/// Returns the player and its position if it's the player's turn.
+pub fn player_turn(app: &mut App) -> Option<(Entity, GridPosition)> {
+    if *app.world.get_resource::<State<AppState>>()?.current()
+        != AppState::DungeonCrawl(TurnState::Turn)
+    {
+        return None;
+    }
+
+    app.world
+        .query_filtered::<(Entity, &GridPosition, &StatusEffects), (With<Player>, With<Initiative>)>()
+        .iter(&app.world)
+        .find(|(_, _, statuses)| !statuses.skips_turn())
+        .map(|(e, pos, _)| (e, *pos))
+}
+
+/// Plays the given number of player turns by walking in random directions
+/// and returns the log, oldest first. Stops early when the run ends.
+pub fn simulate(turns: u32) -> Vec<String> {
+    let mut app = headless_app().app;
+    let mut log = ManualEventReader::<LogMessage>::default();
+    let mut messages = vec![];
+
+    let mut turn = 0;
+    while turn < turns {
+        app.update();
+
+        let events = app.world.get_resource::<Events<LogMessage>>().unwrap();
+        messages.extend(log.iter(events).map(|message| message.1.clone()));
+
+        if *app
+            .world
+            .get_resource::<State<AppState>>()
+            .unwrap()
+            .current()
+            == AppState::EndOfRun
+        {
+            break;
+        }
+
+        if let Some((player, position)) = player_turn(&mut app) {
+            turn += 1;
+            let ev = random_step(&mut app, player, position);
+            send_ev(&mut app, ev);
+        }
+    }
+    messages
+}
+
+fn random_step(app: &mut App, player: Entity, position: GridPosition) -> Ev {
+    let neighbours = app
+        .world
+        .get_resource::<WorldMap>()
+        .unwrap()
+        .neighbours(position);
+    if neighbours.is_empty() {
+        return Ev::Nothing;
+    }
+    let new_pos = neighbours[app
+        .world
+        .get_resource_mut::<GameRng>()
+        .unwrap()
+        .0
+        .gen_range(0..neighbours.len())];
+
+    let world = app.world.get_resource::<WorldMap>().unwrap();
+    if Some(new_pos) == world.stairs {
+        Ev::Descend
+    } else if Some(new_pos) == world.up_stairs {
+        Ev::Ascend
+    } else if world.tiles[new_pos].contains(TileFlags::BLOCKS_MOVEMENT) {
+        world.entities[new_pos]
+            .iter()
+            .find(|e| app.world.get::<Health>(**e).is_some())
+            .map_or(Ev::Nothing, |e| Ev::Attack(player, *e))
+    } else {
+        Ev::Move(player, position, new_pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dungeon_crawl::{
+        Cursor, EnemyAI, GameData, Item, Logs, RunOutcome, Status, StatusEffects, StatusKind,
+    };
+    use bevy::input::{keyboard::KeyboardInput, ElementState};
+
+    fn test_app() -> App {
+        let mut app = headless_app().app;
+        app.world.get_resource_mut::<GameData>().unwrap().seed = 7;
+        app
+    }
+
+    /// Updates the app until it's the player's turn, returning the messages logged on the way.
+    fn run_until_turn(app: &mut App, log: &mut ManualEventReader<LogMessage>) -> Vec<String> {
+        let mut messages = vec![];
+        for _ in 0..1000 {
+            app.update();
+            let events = app.world.get_resource::<Events<LogMessage>>().unwrap();
+            messages.extend(log.iter(events).map(|message| message.1.clone()));
+            if player_turn(app).is_some() {
+                return messages;
+            }
+        }
+        panic!("the player never got a turn");
+    }
+
+    /// A free floor tile next to the player.
+    fn free_neighbour(app: &App, position: GridPosition) -> GridPosition {
+        let world = app.world.get_resource::<WorldMap>().unwrap();
+        world
+            .neighbours(position)
+            .into_iter()
+            .find(|pos| {
+                !world.tiles[*pos].contains(TileFlags::BLOCKS_MOVEMENT)
+                    && Some(*pos) != world.stairs
+                    && Some(*pos) != world.up_stairs
+            })
+            .expect("the player is walled in")
+    }
+
+    fn send_key(app: &mut App, key: KeyCode, state: ElementState) {
+        app.world
+            .get_resource_mut::<Events<KeyboardInput>>()
+            .unwrap()
+            .send(KeyboardInput {
+                scan_code: 0,
+                key_code: Some(key),
+                state,
+            });
+    }
+
+    /// Presses and releases the key over two updates.
+    fn press(app: &mut App, key: KeyCode) {
+        for state in [ElementState::Pressed, ElementState::Released] {
+            send_key(app, key, state);
+            app.update();
+        }
+    }
+
+    /// Aims a health potion at `target` and confirms, returning the newest message of the
+    /// run. The target is made visible first, as it must be to aim there.
+    fn use_potion_on(app: &mut App, target: GridPosition) -> String {
+        // The cursor is part of the UI, which the headless app goes without.
+        app.world.spawn().insert_bundle((target, Cursor));
+        let item = app
+            .world
+            .spawn()
+            .insert(Item(String::from("health potion")))
+            .id();
+        let mut data = app.world.get_resource_mut::<GameData>().unwrap();
+        data.inventory[0] = Some(item);
+        data.selected = Some(0);
+        let mut world = app.world.get_resource_mut::<WorldMap>().unwrap();
+        world.tiles[target].insert(TileFlags::IN_VIEW);
+
+        app.world
+            .get_resource_mut::<State<AppState>>()
+            .unwrap()
+            .push(AppState::Targeting)
+            .unwrap();
+        app.update();
+        // Entering the state snaps the cursor to the nearest target.
+        *app.world
+            .query_filtered::<&mut GridPosition, With<Cursor>>()
+            .iter_mut(&mut app.world)
+            .next()
+            .unwrap() = target;
+        press(app, KeyCode::Return);
+
+        let logs = app.world.get_resource::<Logs>().unwrap();
+        logs.0
+            .front()
+            .map_or(String::new(), |entry| entry.text.clone())
+    }
+
+    #[test]
+    fn simulation_runs() {
+        simulate(200);
+    }
+
+    #[test]
+    fn move_changes_position() {
+        let mut app = test_app();
+        let mut log = ManualEventReader::default();
+        run_until_turn(&mut app, &mut log);
+        let (player, position) = player_turn(&mut app).unwrap();
+
+        let target = free_neighbour(&app, position);
+        send_ev(&mut app, Ev::Move(player, position, target));
+        run_until_turn(&mut app, &mut log);
+
+        assert_eq!(*app.world.get::<GridPosition>(player).unwrap(), target);
+        let world = app.world.get_resource::<WorldMap>().unwrap();
+        assert!(world.entities[target].contains(&player));
+        assert!(!world.entities[position].contains(&player));
+    }
+
+    #[test]
+    fn attack_rolls_against_monster() {
+        let mut app = test_app();
+        let mut log = ManualEventReader::default();
+        run_until_turn(&mut app, &mut log);
+        let (player, position) = player_turn(&mut app).unwrap();
+
+        // Bring a monster next to the player.
+        let target = free_neighbour(&app, position);
+        let (monster, from) = app
+            .world
+            .query_filtered::<(Entity, &GridPosition), With<EnemyAI>>()
+            .iter(&app.world)
+            .map(|(e, pos)| (e, *pos))
+            .next()
+            .expect("the floor has no monsters");
+        *app.world.get_mut::<GridPosition>(monster).unwrap() = target;
+        let mut world = app.world.get_resource_mut::<WorldMap>().unwrap();
+        world.entities[from].retain(|e| *e != monster);
+        world.entities[target].push(monster);
+
+        send_ev(&mut app, Ev::Attack(player, monster));
+        let messages = run_until_turn(&mut app, &mut log);
+
+        assert!(
+            messages.iter().any(|m| m.starts_with("Player attacks")),
+            "{:?}",
+            messages
+        );
+    }
+
+    #[test]
+    fn descend_and_ascend_keep_floors() {
+        let mut app = test_app();
+        let mut log = ManualEventReader::default();
+        run_until_turn(&mut app, &mut log);
+        let stairs = app.world.get_resource::<WorldMap>().unwrap().stairs;
+
+        send_ev(&mut app, Ev::Descend);
+        run_until_turn(&mut app, &mut log);
+        assert_eq!(app.world.get_resource::<GameData>().unwrap().floor, 2);
+        // The player arrives on the up stairs of a new floor.
+        let (_, position) = player_turn(&mut app).unwrap();
+        let up_stairs = app.world.get_resource::<WorldMap>().unwrap().up_stairs;
+        assert_eq!(Some(position), up_stairs);
+
+        send_ev(&mut app, Ev::Ascend);
+        run_until_turn(&mut app, &mut log);
+        assert_eq!(app.world.get_resource::<GameData>().unwrap().floor, 1);
+        // Back on the same floor, on the stairs that were taken.
+        let (_, position) = player_turn(&mut app).unwrap();
+        assert_eq!(app.world.get_resource::<WorldMap>().unwrap().stairs, stairs);
+        assert_eq!(Some(position), stairs);
+    }
+
+    #[test]
+    fn statuses_last_across_stairs() {
+        let mut app = test_app();
+        let mut log = ManualEventReader::default();
+        run_until_turn(&mut app, &mut log);
+        let (player, _) = player_turn(&mut app).unwrap();
+        app.world.get_mut::<StatusEffects>(player).unwrap().0 = vec![Status {
+            kind: StatusKind::Slow,
+            duration: 10,
+            potency: 0,
+        }];
+
+        let player_slowed = |app: &mut App| {
+            let (player, _) = player_turn(app).unwrap();
+            app.world
+                .get::<StatusEffects>(player)
+                .unwrap()
+                .has(StatusKind::Slow)
+        };
+        // Onto a new floor, then back to a visited one.
+        send_ev(&mut app, Ev::Descend);
+        run_until_turn(&mut app, &mut log);
+        assert!(player_slowed(&mut app));
+        send_ev(&mut app, Ev::Ascend);
+        run_until_turn(&mut app, &mut log);
+        assert!(player_slowed(&mut app));
+    }
+
+    #[test]
+    fn poison_death_is_not_by_own_hand() {
+        // The game data is reset when the run ends, so keep the outcome before that.
+        struct Outcome(Option<String>);
+        fn keep_outcome(data: Res<GameData>, mut outcome: ResMut<Outcome>) {
+            outcome.0 = data.outcome.as_ref().map(RunOutcome::to_string);
+        }
+        let mut builder = headless_app();
+        builder.insert_resource(Outcome(None)).add_system_set(
+            SystemSet::on_enter(AppState::EndOfRun).with_system(keep_outcome.system()),
+        );
+        let mut app = builder.app;
+        app.world.get_resource_mut::<GameData>().unwrap().seed = 7;
+
+        let mut log = ManualEventReader::default();
+        run_until_turn(&mut app, &mut log);
+        let (player, _) = player_turn(&mut app).unwrap();
+
+        app.world.get_mut::<Health>(player).unwrap().current = 1;
+        app.world.get_mut::<StatusEffects>(player).unwrap().0 = vec![Status {
+            kind: StatusKind::Poison,
+            duration: 5,
+            potency: 1,
+        }];
+        send_ev(&mut app, Ev::Nothing);
+        for _ in 0..100 {
+            app.update();
+        }
+
+        let outcome = &app.world.get_resource::<Outcome>().unwrap().0;
+        assert_eq!(outcome.as_deref(), Some("Died while poisoned"));
+    }
+
+    #[test]
+    fn aiming_at_nothing_says_why() {
+        let mut app = test_app();
+        let mut log = ManualEventReader::default();
+        run_until_turn(&mut app, &mut log);
+        let (_, position) = player_turn(&mut app).unwrap();
+
+        let target = free_neighbour(&app, position);
+        assert_eq!(
+            use_potion_on(&mut app, target),
+            "There's nothing to use that on."
+        );
+    }
+
+    /// The tile `distance` steps east of the player, or west near the edge of the map.
+    fn tile_across(app: &App, position: GridPosition, distance: i32) -> GridPosition {
+        let world = app.world.get_resource::<WorldMap>().unwrap();
+        let east = GridPosition {
+            x: position.x + distance,
+            ..position
+        };
+        if world.tiles.get(east.x, east.y).is_some() {
+            east
+        } else {
+            GridPosition {
+                x: position.x - distance,
+                ..position
+            }
+        }
+    }
+
+    #[test]
+    fn aiming_out_of_range_says_why() {
+        let mut app = test_app();
+        let mut log = ManualEventReader::default();
+        run_until_turn(&mut app, &mut log);
+        let (_, position) = player_turn(&mut app).unwrap();
+
+        // Health potions reach 4 tiles.
+        let target = tile_across(&app, position, 5);
+        assert_eq!(use_potion_on(&mut app, target), "That's out of range.");
+    }
+
+    #[test]
+    fn aiming_through_a_wall_says_why() {
+        let mut app = test_app();
+        let mut log = ManualEventReader::default();
+        run_until_turn(&mut app, &mut log);
+        let (_, position) = player_turn(&mut app).unwrap();
+
+        let target = tile_across(&app, position, 2);
+        let between = GridPosition {
+            x: (position.x + target.x) / 2,
+            ..position
+        };
+        let mut world = app.world.get_resource_mut::<WorldMap>().unwrap();
+        world.tiles[between].insert(TileFlags::BLOCKS_VISION);
+        assert_eq!(use_potion_on(&mut app, target), "Something is in the way.");
+    }
+
+    #[test]
+    fn regeneration_does_not_stop_resting() {
+        let mut app = test_app();
+        let mut log = ManualEventReader::default();
+        run_until_turn(&mut app, &mut log);
+        let (player, _) = player_turn(&mut app).unwrap();
+        app.world.get_mut::<Health>(player).unwrap().current = 1;
+        app.world.get_mut::<StatusEffects>(player).unwrap().0 = vec![Status {
+            kind: StatusKind::Regeneration,
+            duration: 5,
+            potency: 1,
+        }];
+
+        // The whole rest can take a single update.
+        send_key(&mut app, KeyCode::R, ElementState::Pressed);
+        let messages = run_until_turn(&mut app, &mut log);
+        send_key(&mut app, KeyCode::R, ElementState::Released);
+        assert!(
+            messages
+                .iter()
+                .any(|m| m.ends_with("is healed by 1 health.")),
+            "{:?}",
+            messages
+        );
+        assert!(
+            !messages.iter().any(|m| m.starts_with("You stop resting")),
+            "{:?}",
+            messages
+        );
+    }
+}
@@ -0,0 +1,273 @@
+use crate::{
+    dungeon_crawl::{GameData, RunOutcome},
+    storage, AppState,
+};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HighScore {
+    pub score: u32,
+    pub outcome: String,
+    pub floor: u32,
+    pub level: u32,
+    pub kills: u32,
+    pub seed: u64,
+}
+
+impl HighScore {
+    pub fn new(data: &GameData) -> Self {
+        let kills = data.kills.values().sum();
+        Self {
+            score: data.floor * 100 + data.level * 50 + kills * 10,
+            outcome: data
+                .outcome
+                .as_ref()
+                .map_or(String::new(), RunOutcome::to_string),
+            floor: data.floor,
+            level: data.level,
+            kills,
+            seed: data.seed,
+        }
+    }
+}
+
+/// Best runs, highest score first. Kept in a file on native builds and in
+/// `localStorage` in the browser.
+#[derive(Default, Serialize, Deserialize)]
+pub struct HighScores(pub Vec<HighScore>);
+
+impl HighScores {
+    const MAX_ENTRIES: usize = 10;
+    const STORAGE_NAME: &'static str = "highscores.ron";
+
+    pub fn load() -> Self {
+        storage::read(Self::STORAGE_NAME)
+            .and_then(|s| ron::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn store(&self) {
+        let result = ron::to_string(self)
+            .map_err(|e| e.to_string())
+            .and_then(|s| storage::write(Self::STORAGE_NAME, &s));
+        if let Err(err) = result {
+            error!("Couldn't save the high scores: {}", err);
+        }
+    }
+
+    pub fn add(&mut self, entry: HighScore) {
+        // Stable sort so earlier runs stay ahead on ties.
+        self.0.push(entry);
+        self.0.sort_by_key(|s| Reverse(s.score));
+        self.0.truncate(Self::MAX_ENTRIES);
+    }
+}
+
+/// Adds the run that just ended to the table.
+pub fn record_score(data: Res<GameData>) {
+    let mut scores = HighScores::load();
+    scores.add(HighScore::new(&data));
+    scores.store();
+}
+
+pub struct HighScoresPlugin;
+impl Plugin for HighScoresPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_enter(AppState::HighScores).with_system(high_scores_ui_create.system()),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::HighScores)
+                .with_system(high_scores_interaction.system()),
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::HighScores).with_system(high_scores_cleanup.system()),
+        );
+    }
+}
+
+pub struct HighScoresCanvas;
+pub struct HighScoresBackButton;
+
+pub fn high_scores_ui_create(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let scores = HighScores::load();
+    let table = if scores.0.is_empty() {
+        String::from("No runs yet")
+    } else {
+        scores
+            .0
+            .iter()
+            .enumerate()
+            .map(|(i, s)| {
+                format!(
+                    "{}. {} - {} on floor {}, level {}, {} kills",
+                    i + 1,
+                    s.score,
+                    s.outcome,
+                    s.floor,
+                    s.level,
+                    s.kills
+                )
+            })
+            .intersperse(String::from("\n"))
+            .collect()
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            material: materials.add(Color::hex("101010").unwrap().into()),
+            ..Default::default()
+        })
+        .insert(HighScoresCanvas)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    margin: Rect::all(Val::Px(50.0)),
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    "SCORES",
+                    TextStyle {
+                        font: asset_server.load("Roboto/Roboto-Regular.ttf"),
+                        font_size: 100.0,
+                        color: Color::hex("826007").unwrap(),
+                    },
+                    TextAlignment::default(),
+                ),
+                ..Default::default()
+            });
+
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    table,
+                    TextStyle {
+                        font: asset_server.load("Roboto/Roboto-Regular.ttf"),
+                        font_size: 40.0,
+                        color: Color::WHITE,
+                    },
+                    TextAlignment::default(),
+                ),
+                ..Default::default()
+            });
+
+            parent
+                .spawn_bundle(ButtonBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(50.0)),
+                        ..Default::default()
+                    },
+                    material: materials.add(Color::hex("101010").unwrap().into()),
+                    ..Default::default()
+                })
+                .insert(HighScoresBackButton)
+                .with_children(|parent| {
+                    parent.spawn_bundle(TextBundle {
+                        text: Text::with_section(
+                            "BACK",
+                            TextStyle {
+                                font: asset_server.load("Roboto/Roboto-Regular.ttf"),
+                                font_size: 100.0,
+                                color: Color::WHITE,
+                            },
+                            TextAlignment::default(),
+                        ),
+                        ..Default::default()
+                    });
+                });
+        });
+}
+
+pub fn high_scores_interaction(
+    q: Query<&Interaction, With<HighScoresBackButton>>,
+    keys: Res<Input<KeyCode>>,
+    mut app_state: ResMut<State<AppState>>,
+) {
+    let clicked = q.iter().any(|i| *i == Interaction::Clicked);
+    if clicked || keys.just_pressed(KeyCode::Escape) {
+        app_state.set(AppState::MainMenu).unwrap();
+    }
+}
+
+pub fn high_scores_cleanup(mut commands: Commands, q: Query<Entity, With<HighScoresCanvas>>) {
+    commands.entity(q.single().unwrap()).despawn_recursive();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(score: u32, seed: u64) -> HighScore {
+        HighScore {
+            score,
+            outcome: String::from("Killed by a rat"),
+            floor: 1,
+            level: 1,
+            kills: 0,
+            seed,
+        }
+    }
+
+    fn seeds(scores: &HighScores) -> Vec<u64> {
+        scores.0.iter().map(|s| s.seed).collect()
+    }
+
+    #[test]
+    fn highest_score_first_and_earlier_runs_win_ties() {
+        let mut scores = HighScores::default();
+        scores.add(entry(100, 0));
+        scores.add(entry(300, 1));
+        scores.add(entry(100, 2));
+        scores.add(entry(200, 3));
+        assert_eq!(seeds(&scores), vec![1, 3, 0, 2]);
+    }
+
+    #[test]
+    fn only_the_best_runs_are_kept() {
+        let mut scores = HighScores::default();
+        for seed in 0..15 {
+            scores.add(entry(seed as u32 * 10, seed));
+        }
+        assert_eq!(scores.0.len(), HighScores::MAX_ENTRIES);
+        assert_eq!(seeds(&scores), (5..15).rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn score_counts_floor_level_and_kills() {
+        let mut data = GameData {
+            floor: 3,
+            level: 2,
+            outcome: Some(RunOutcome::Died { killer: None }),
+            ..Default::default()
+        };
+        data.kills.insert(String::from("rat"), 2);
+        data.kills.insert(String::from("goblin"), 1);
+        let score = HighScore::new(&data);
+        assert_eq!(score.score, 300 + 100 + 30);
+        assert_eq!(score.kills, 3);
+        assert_eq!(score.outcome, "Died by their own hand");
+    }
+
+    #[test]
+    fn stored_scores_load_back_in_order() {
+        let mut scores = HighScores::default();
+        scores.add(entry(100, 0));
+        scores.add(entry(200, 1));
+        let stored = ron::to_string(&scores).unwrap();
+        let loaded: HighScores = ron::from_str(&stored).unwrap();
+        assert_eq!(seeds(&loaded), seeds(&scores));
+        assert_eq!(loaded.0[0].outcome, scores.0[0].outcome);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::{
+    dungeon_crawl::{CombatStats, GameData, GameRng, Item, Status, StatusKind},
+    world_map::{GridPosition, WorldMap},
+};
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Slot {
+    Weapon,
+    Armor,
+}
+
+/// Which creatures a used item affects.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Targeting {
+    #[default]
+    OnSelf,
+    /// The creature on the clicked tile.
+    Single,
+    /// All creatures within the radius around the clicked tile.
+    Area(i32),
+}
+
+impl Targeting {
+    /// Tiles affected when aiming at `target`. Walls stop the blast, so only the
+    /// tiles in sight of the target are hit.
+    pub fn area(&self, world: &WorldMap, target: GridPosition) -> Vec<GridPosition> {
+        let radius = match self {
+            Targeting::OnSelf | Targeting::Single => 0,
+            Targeting::Area(radius) => *radius,
+        };
+        let mut area = vec![];
+        for x in -radius..=radius {
+            for y in -radius..=radius {
+                let tile = GridPosition {
+                    x: target.x + x,
+                    y: target.y + y,
+                };
+                if world.tiles.get(tile.x, tile.y).is_some() && world.line_of_sight(target, tile) {
+                    area.push(tile);
+                }
+            }
+        }
+        area
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Effect {
+    Heal(i32),
+    Damage(i32),
+    Status(Status),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ItemTemplate {
+    pub name: String,
+    /// Flavour text shown when examining the item.
+    #[cfg(not(feature = "headless"))]
+    #[serde(default)]
+    pub description: String,
+    pub texture: String,
+    pub color: String,
+    #[serde(default)]
+    pub slot: Option<Slot>,
+    /// Added to the wearer's stats while equipped.
+    #[serde(default)]
+    pub stats: CombatStats,
+    #[serde(default)]
+    pub targeting: Targeting,
+    /// How far from the user the item can be aimed, unlimited when missing.
+    #[serde(default)]
+    pub range: Option<i32>,
+    #[serde(default)]
+    pub effects: Vec<Effect>,
+    pub min_floor: u32,
+    pub spawn_weight: i32,
+}
+
+impl ItemTemplate {
+    pub fn color(&self) -> Color {
+        Color::hex(&self.color).unwrap_or(Color::WHITE)
+    }
+
+    pub fn in_range(&self, from: GridPosition, to: GridPosition) -> bool {
+        self.range
+            .is_none_or(|range| (to.x - from.x).pow(2) + (to.y - from.y).pow(2) <= range.pow(2))
+    }
+
+    /// Meant to be used on yourself or allies rather than on enemies.
+    pub fn is_beneficial(&self) -> bool {
+        self.effects.iter().all(|effect| match effect {
+            Effect::Heal(_) => true,
+            Effect::Damage(_) => false,
+            Effect::Status(status) => {
+                matches!(status.kind, StatusKind::Regeneration | StatusKind::Haste)
+            }
+        })
+    }
+}
+
+/// Every item that can be found, loaded from `assets/data/items.ron` at startup.
+/// Editing the file and restarting is enough to change an item. In the browser the
+/// file is built into the game.
+pub struct ItemTable(pub Vec<ItemTemplate>);
+
+impl Default for ItemTable {
+    fn default() -> Self {
+        #[cfg(not(feature = "web"))]
+        let text = std::fs::read_to_string(
+            bevy::asset::FileAssetIo::get_root_path().join("assets/data/items.ron"),
+        )
+        .expect("Missing assets/data/items.ron");
+        #[cfg(feature = "web")]
+        let text = include_str!("../assets/data/items.ron");
+
+        let table = ron::from_str(&text).expect("Invalid assets/data/items.ron");
+        Self(table)
+    }
+}
+
+impl ItemTable {
+    pub fn get(&self, name: &str) -> Option<&ItemTemplate> {
+        self.0.iter().find(|i| i.name == name)
+    }
+
+    /// Picks a random item that can appear on the given floor, using spawn weights.
+    pub fn random_for_floor(&self, floor: u32, rng: &mut GameRng) -> &ItemTemplate {
+        let candidates: Vec<_> = self
+            .0
+            .iter()
+            .filter(|i| i.min_floor <= floor)
+            .map(|i| (i, i.spawn_weight))
+            .collect();
+        rng.weighted(&candidates)
+    }
+}
+
+/// Looks up the templates of item entities.
+#[derive(SystemParam)]
+pub struct ItemTemplates<'a> {
+    items: Query<'a, &'static Item>,
+    table: Res<'a, ItemTable>,
+}
+
+impl<'a> ItemTemplates<'a> {
+    pub fn get(&self, item: Entity) -> &ItemTemplate {
+        self.table.get(&self.items.get(item).unwrap().0).unwrap()
+    }
+
+    /// Stat bonus of the player's equipped weapon and armor.
+    pub fn equipment(&self, data: &GameData) -> CombatStats {
+        data.weapon
+            .iter()
+            .chain(data.armor.iter())
+            .map(|item| self.get(*item).stats)
+            .fold(CombatStats::default(), |a, b| a + b)
+    }
+}
@@ -1,23 +1,44 @@
-#![feature(iter_intersperse)]
+#![cfg_attr(not(feature = "headless"), feature(iter_intersperse))]
 #![feature(option_result_contains)]
 
 mod bundles;
+mod controls;
 mod dungeon_crawl;
+#[cfg(any(test, feature = "headless"))]
+mod headless;
+#[cfg(not(feature = "headless"))]
+mod high_scores;
+mod items;
+mod monsters;
+mod storage;
 mod world_generation;
 mod world_map;
 
 use bevy::{app::AppExit, prelude::*};
-use dungeon_crawl::TurnState;
+use dungeon_crawl::{save::save_exists, GameData, TurnState};
+#[cfg(not(feature = "headless"))]
 use world_map::Grid;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum AppState {
     MainMenu,
+    HighScores,
+    Controls,
     WorldGeneration,
+    LoadGame,
+    LoadFloor,
     DungeonCrawlEnter,
     DungeonCrawl(TurnState),
+    /// Pushed on top of `DungeonCrawl` so the game continues where it was.
+    MessageHistory,
+    /// Examining the map with a keyboard cursor, pushed like `MessageHistory`.
+    Look,
+    /// Aiming the selected item, pushed like `MessageHistory`.
+    Targeting,
     DungeonCrawlExitToMenu,
+    EndOfRun,
     DungeonCrawlDescend,
+    DungeonCrawlAscend,
 }
 
 #[cfg_attr(target_arch = "wasm32", global_allocator)]
@@ -26,6 +47,14 @@ static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
 pub struct UiCamera;
 
+#[cfg(feature = "headless")]
+fn main() {
+    for message in headless::simulate(1000) {
+        println!("{}", message);
+    }
+}
+
+#[cfg(not(feature = "headless"))]
 fn main() {
     // When building for WASM, print panics to the browser console
     #[cfg(target_arch = "wasm32")]
@@ -62,24 +91,38 @@ fn main() {
     app.add_plugin(bevy_webgl2::WebGL2Plugin);
 
     app.add_state(AppState::MainMenu)
+        .init_resource::<SeedInput>()
         .add_system_set(
             SystemSet::on_enter(AppState::MainMenu).with_system(main_menu_ui_create.system()),
         )
         .add_system_set(
-            SystemSet::on_update(AppState::MainMenu).with_system(main_menu_interaction.system()),
+            SystemSet::on_update(AppState::MainMenu)
+                .with_system(main_menu_interaction.system())
+                .with_system(main_menu_seed_input.system()),
         )
         .add_system_set(
             SystemSet::on_exit(AppState::MainMenu).with_system(main_menu_cleanup.system()),
         )
         .add_plugin(dungeon_crawl::DungeonCrawlPlugin)
+        .add_plugin(dungeon_crawl::PersistencePlugin)
+        .add_plugin(dungeon_crawl::DungeonCrawlUIPlugin)
+        .add_plugin(high_scores::HighScoresPlugin)
+        .add_plugin(controls::ControlsPlugin)
         .add_plugins(world_generation::WorldGenerationPlugins);
 
     app.run();
 }
 
 pub struct MainMenuCanvas;
+pub struct MainMenuSeedText;
+/// Digits typed in the main menu. Empty means a random seed.
+#[derive(Default)]
+pub struct SeedInput(pub String);
 pub enum MainMenuButton {
+    Continue,
     Play,
+    Scores,
+    Controls,
     Quit,
 }
 
@@ -87,18 +130,55 @@ pub fn main_menu_interaction(
     q: Query<(&Interaction, &MainMenuButton)>,
     mut app_state: ResMut<State<AppState>>,
     mut app_exit_events: EventWriter<AppExit>,
+    mut data: ResMut<GameData>,
+    seed_input: Res<SeedInput>,
 ) {
     for (i, b) in q.iter() {
         match (i, b) {
+            (Interaction::Clicked, MainMenuButton::Continue) => {
+                app_state.set(AppState::LoadGame).unwrap();
+            }
             (Interaction::Clicked, MainMenuButton::Play) => {
+                if let Ok(seed) = seed_input.0.parse() {
+                    data.seed = seed;
+                }
                 app_state.set(AppState::WorldGeneration).unwrap();
             }
+            (Interaction::Clicked, MainMenuButton::Scores) => {
+                app_state.set(AppState::HighScores).unwrap();
+            }
+            (Interaction::Clicked, MainMenuButton::Controls) => {
+                app_state.set(AppState::Controls).unwrap();
+            }
             (Interaction::Clicked, MainMenuButton::Quit) => app_exit_events.send(AppExit),
             _ => {}
         }
     }
 }
 
+pub fn main_menu_seed_input(
+    mut chars: EventReader<ReceivedCharacter>,
+    keys: Res<Input<KeyCode>>,
+    mut seed_input: ResMut<SeedInput>,
+    mut text: Query<&mut Text, With<MainMenuSeedText>>,
+) {
+    for c in chars.iter() {
+        // 19 digits always fit in u64
+        if c.char.is_ascii_digit() && seed_input.0.len() < 19 {
+            seed_input.0.push(c.char);
+        }
+    }
+    if keys.just_pressed(KeyCode::Back) {
+        seed_input.0.pop();
+    }
+
+    text.single_mut().unwrap().sections[0].value = if seed_input.0.is_empty() {
+        String::from("SEED: random (type to enter)")
+    } else {
+        format!("SEED: {}", seed_input.0)
+    };
+}
+
 pub fn main_menu_ui_create(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
@@ -118,6 +198,33 @@ pub fn main_menu_ui_create(
         })
         .insert(MainMenuCanvas)
         .with_children(|parent| {
+            if save_exists() {
+                parent
+                    .spawn_bundle(ButtonBundle {
+                        style: Style {
+                            margin: Rect::all(Val::Px(50.0)),
+                            ..Default::default()
+                        },
+                        material: materials.add(Color::hex("101010").unwrap().into()),
+                        ..Default::default()
+                    })
+                    .insert(MainMenuButton::Continue)
+                    .with_children(|parent| {
+                        parent.spawn_bundle(TextBundle {
+                            text: Text::with_section(
+                                "CONTINUE",
+                                TextStyle {
+                                    font: asset_server.load("Roboto/Roboto-Regular.ttf"),
+                                    font_size: 100.0,
+                                    color: Color::WHITE,
+                                },
+                                TextAlignment::default(),
+                            ),
+                            ..Default::default()
+                        });
+                    });
+            }
+
             parent
                 .spawn_bundle(ButtonBundle {
                     style: Style {
@@ -143,6 +250,71 @@ pub fn main_menu_ui_create(
                     });
                 });
 
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "SEED: random",
+                        TextStyle {
+                            font: asset_server.load("Roboto/Roboto-Regular.ttf"),
+                            font_size: 40.0,
+                            color: Color::hex("826007").unwrap(),
+                        },
+                        TextAlignment::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(MainMenuSeedText);
+
+            parent
+                .spawn_bundle(ButtonBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(50.0)),
+                        ..Default::default()
+                    },
+                    material: materials.add(Color::hex("101010").unwrap().into()),
+                    ..Default::default()
+                })
+                .insert(MainMenuButton::Scores)
+                .with_children(|parent| {
+                    parent.spawn_bundle(TextBundle {
+                        text: Text::with_section(
+                            "SCORES",
+                            TextStyle {
+                                font: asset_server.load("Roboto/Roboto-Regular.ttf"),
+                                font_size: 100.0,
+                                color: Color::WHITE,
+                            },
+                            TextAlignment::default(),
+                        ),
+                        ..Default::default()
+                    });
+                });
+
+            parent
+                .spawn_bundle(ButtonBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(50.0)),
+                        ..Default::default()
+                    },
+                    material: materials.add(Color::hex("101010").unwrap().into()),
+                    ..Default::default()
+                })
+                .insert(MainMenuButton::Controls)
+                .with_children(|parent| {
+                    parent.spawn_bundle(TextBundle {
+                        text: Text::with_section(
+                            "CONTROLS",
+                            TextStyle {
+                                font: asset_server.load("Roboto/Roboto-Regular.ttf"),
+                                font_size: 100.0,
+                                color: Color::WHITE,
+                            },
+                            TextAlignment::default(),
+                        ),
+                        ..Default::default()
+                    });
+                });
+
             parent
                 .spawn_bundle(ButtonBundle {
                     style: Style {
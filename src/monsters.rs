@@ -0,0 +1,117 @@
+use crate::dungeon_crawl::{CombatStats, GameRng};
+use bevy::prelude::*;
+use serde::Deserialize;
+
+/// How a monster behaves once it has noticed the player.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum AIType {
+    /// Chases the player and attacks in melee.
+    Melee,
+    /// Stays in place and only attacks when the player is adjacent.
+    Sentry,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MonsterTemplate {
+    pub name: String,
+    /// Flavour text shown when examining the monster.
+    #[cfg(not(feature = "headless"))]
+    #[serde(default)]
+    pub description: String,
+    pub texture: String,
+    pub color: String,
+    pub hp: i32,
+    pub stats: CombatStats,
+    /// Energy gained per tick, `Speed::NORMAL` is as fast as the player.
+    pub speed: i32,
+    pub ai: AIType,
+    /// How far the monster sees the player from.
+    #[serde(default = "MonsterTemplate::default_view_radius")]
+    pub view_radius: i32,
+    /// Health at which the monster runs from the player, it never does by default.
+    #[serde(default)]
+    pub flees_at: i32,
+    pub min_floor: u32,
+    pub spawn_weight: i32,
+    /// Bosses never spawn randomly, one waits on the final floor.
+    #[serde(default)]
+    pub boss: bool,
+}
+
+impl MonsterTemplate {
+    fn default_view_radius() -> i32 {
+        6
+    }
+
+    pub fn color(&self) -> Color {
+        Color::hex(&self.color).unwrap_or(Color::WHITE)
+    }
+}
+
+/// All monster templates from `assets/data/monsters.ron`, read when the game starts so
+/// stats and spawn rules can be tuned without a rebuild. The web build has no files to
+/// read and carries its own copy.
+pub struct MonsterTable(pub Vec<MonsterTemplate>);
+
+impl Default for MonsterTable {
+    fn default() -> Self {
+        #[cfg(not(feature = "web"))]
+        let text = std::fs::read_to_string(
+            bevy::asset::FileAssetIo::get_root_path().join("assets/data/monsters.ron"),
+        )
+        .expect("Missing assets/data/monsters.ron");
+        #[cfg(feature = "web")]
+        let text = include_str!("../assets/data/monsters.ron");
+
+        let table = ron::from_str(&text).expect("Invalid assets/data/monsters.ron");
+        Self(table)
+    }
+}
+
+impl MonsterTable {
+    pub fn get(&self, name: &str) -> Option<&MonsterTemplate> {
+        self.0.iter().find(|m| m.name == name)
+    }
+
+    /// Picks a random monster that can appear on the given floor, using spawn weights.
+    pub fn random_for_floor(&self, floor: u32, rng: &mut GameRng) -> &MonsterTemplate {
+        let candidates: Vec<_> = self
+            .0
+            .iter()
+            .filter(|m| !m.boss && m.min_floor <= floor)
+            .map(|m| (m, m.spawn_weight))
+            .collect();
+        rng.weighted(&candidates)
+    }
+
+    pub fn boss(&self) -> &MonsterTemplate {
+        self.0
+            .iter()
+            .find(|m| m.boss)
+            .expect("No boss in assets/data/monsters.ron")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn view_radius_defaults_when_left_out() {
+        let template: MonsterTemplate = ron::from_str(
+            r#"(
+                name: "rat",
+                texture: "rat.png",
+                color: "FFFFFF",
+                hp: 1,
+                stats: (),
+                speed: 100,
+                ai: Melee,
+                min_floor: 1,
+                spawn_weight: 1,
+            )"#,
+        )
+        .unwrap();
+        assert_eq!(template.view_radius, 6);
+    }
+}
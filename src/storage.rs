@@ -0,0 +1,66 @@
+//! Small text files kept between runs, like the saved game, the high scores and the controls.
+//! They're next to the game on native builds and in `localStorage` in the browser, where the
+//! file name is the key.
+
+#[cfg(not(feature = "web"))]
+mod native {
+    use std::{fs, path::Path};
+
+    pub fn read(name: &str) -> Option<String> {
+        fs::read_to_string(name).ok()
+    }
+
+    #[cfg(not(feature = "headless"))]
+    pub fn write(name: &str, s: &str) -> Result<(), String> {
+        fs::write(name, s).map_err(|e| e.to_string())
+    }
+
+    pub fn exists(name: &str) -> bool {
+        Path::new(name).exists()
+    }
+
+    #[cfg(not(feature = "headless"))]
+    pub fn remove(name: &str) {
+        let _ = fs::remove_file(name);
+    }
+}
+
+#[cfg(not(feature = "web"))]
+pub use native::{exists, read};
+#[cfg(all(not(feature = "web"), not(feature = "headless")))]
+pub use native::{remove, write};
+
+#[cfg(feature = "web")]
+mod web {
+    fn local_storage() -> Option<web_sys::Storage> {
+        web_sys::window()?.local_storage().ok()?
+    }
+
+    pub fn read(name: &str) -> Option<String> {
+        local_storage()?.get_item(name).ok()?
+    }
+
+    #[cfg(not(feature = "headless"))]
+    pub fn write(name: &str, s: &str) -> Result<(), String> {
+        local_storage()
+            .ok_or_else(|| String::from("localStorage is unavailable"))?
+            .set_item(name, s)
+            .map_err(|e| format!("{:?}", e))
+    }
+
+    pub fn exists(name: &str) -> bool {
+        read(name).is_some()
+    }
+
+    #[cfg(not(feature = "headless"))]
+    pub fn remove(name: &str) {
+        if let Some(storage) = local_storage() {
+            let _ = storage.remove_item(name);
+        }
+    }
+}
+
+#[cfg(feature = "web")]
+pub use web::{exists, read};
+#[cfg(all(feature = "web", not(feature = "headless")))]
+pub use web::{remove, write};
@@ -0,0 +1,99 @@
+use super::{split_into_zones, MapGenerator, TileType, MAP_SIZE};
+use crate::{
+    dungeon_crawl::{GameData, GameRng},
+    world_map::Array2D,
+};
+use rand::Rng;
+
+const MIN_LEAF_SIZE: i32 = 8;
+const MIN_ROOM_SIZE: i32 = 3;
+
+/// Rooms connected with corridors, made by recursively splitting the map in two.
+pub struct Bsp;
+impl MapGenerator for Bsp {
+    fn generate(&self, _data: &GameData, rng: &mut GameRng) -> (Array2D<TileType>, usize) {
+        let mut tile_map = Array2D::<TileType>::with_elem(MAP_SIZE, MAP_SIZE, TileType::Dead);
+        split(&mut tile_map, (2, 2), (MAP_SIZE - 4, MAP_SIZE - 4), rng);
+
+        let zone_count = split_into_zones(&mut tile_map);
+        (tile_map, zone_count)
+    }
+}
+
+/// Returns the center of one of the rooms inside the leaf, so the sibling leaf can connect to it.
+fn split(
+    map: &mut Array2D<TileType>,
+    (x, y): (i32, i32),
+    (w, h): (i32, i32),
+    rng: &mut GameRng,
+) -> (i32, i32) {
+    let can_split_x = w >= 2 * MIN_LEAF_SIZE;
+    let can_split_y = h >= 2 * MIN_LEAF_SIZE;
+    if !can_split_x && !can_split_y {
+        return room(map, (x, y), (w, h), rng);
+    }
+
+    let vertical = if can_split_x && can_split_y {
+        rng.0.gen()
+    } else {
+        can_split_x
+    };
+
+    let (a, b) = if vertical {
+        let cut = rng.0.gen_range(MIN_LEAF_SIZE..=w - MIN_LEAF_SIZE);
+        (
+            split(map, (x, y), (cut, h), rng),
+            split(map, (x + cut, y), (w - cut, h), rng),
+        )
+    } else {
+        let cut = rng.0.gen_range(MIN_LEAF_SIZE..=h - MIN_LEAF_SIZE);
+        (
+            split(map, (x, y), (w, cut), rng),
+            split(map, (x, y + cut), (w, h - cut), rng),
+        )
+    };
+
+    corridor(map, a, b, rng);
+    if rng.0.gen() {
+        a
+    } else {
+        b
+    }
+}
+
+fn room(
+    map: &mut Array2D<TileType>,
+    (x, y): (i32, i32),
+    (w, h): (i32, i32),
+    rng: &mut GameRng,
+) -> (i32, i32) {
+    // Leave a wall on each side so that rooms in neighbouring leaves don't merge.
+    let room_w = rng.0.gen_range(MIN_ROOM_SIZE..=w - 2);
+    let room_h = rng.0.gen_range(MIN_ROOM_SIZE..=h - 2);
+    let room_x = x + rng.0.gen_range(1..=w - room_w - 1);
+    let room_y = y + rng.0.gen_range(1..=h - room_h - 1);
+
+    for i in room_x..room_x + room_w {
+        for j in room_y..room_y + room_h {
+            map[[i, j]] = TileType::Alive(0);
+        }
+    }
+
+    (room_x + room_w / 2, room_y + room_h / 2)
+}
+
+fn corridor(
+    map: &mut Array2D<TileType>,
+    (x1, y1): (i32, i32),
+    (x2, y2): (i32, i32),
+    rng: &mut GameRng,
+) {
+    let corner = if rng.0.gen() { (x2, y1) } else { (x1, y2) };
+    for &((from_x, from_y), (to_x, to_y)) in &[((x1, y1), corner), (corner, (x2, y2))] {
+        for x in i32::min(from_x, to_x)..=i32::max(from_x, to_x) {
+            for y in i32::min(from_y, to_y)..=i32::max(from_y, to_y) {
+                map[[x, y]] = TileType::Alive(0);
+            }
+        }
+    }
+}
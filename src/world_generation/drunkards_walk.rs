@@ -0,0 +1,42 @@
+use super::{split_into_zones, MapGenerator, TileType, MAP_SIZE};
+use crate::{
+    dungeon_crawl::{GameData, GameRng},
+    world_map::Array2D,
+};
+use rand::Rng;
+
+const DRUNKARD_LIFETIME: u32 = 100;
+
+/// Winding tunnels dug by drunkards stumbling around in random directions.
+pub struct DrunkardsWalk;
+impl MapGenerator for DrunkardsWalk {
+    fn generate(&self, data: &GameData, rng: &mut GameRng) -> (Array2D<TileType>, usize) {
+        let (min, max) = data.floor_map_size();
+        let target_size = rng.0.gen_range(min..=max) as usize;
+
+        let mut tile_map = Array2D::<TileType>::with_elem(MAP_SIZE, MAP_SIZE, TileType::Dead);
+        let mut dug = vec![(MAP_SIZE / 2, MAP_SIZE / 2)];
+        tile_map[[MAP_SIZE / 2, MAP_SIZE / 2]] = TileType::Alive(0);
+
+        while dug.len() < target_size {
+            // Every drunkard starts on an already dug tile so the map stays connected.
+            let (mut x, mut y) = dug[rng.0.gen_range(0..dug.len())];
+            for _ in 0..DRUNKARD_LIFETIME {
+                let (i, j) = [(0, 1), (1, 0), (-1, 0), (0, -1)][rng.0.gen_range(0..4)];
+                x = (x + i).clamp(2, MAP_SIZE - 3);
+                y = (y + j).clamp(2, MAP_SIZE - 3);
+
+                if tile_map[[x, y]] == TileType::Dead {
+                    tile_map[[x, y]] = TileType::Alive(0);
+                    dug.push((x, y));
+                    if dug.len() >= target_size {
+                        break;
+                    }
+                }
+            }
+        }
+
+        let zone_count = split_into_zones(&mut tile_map);
+        (tile_map, zone_count)
+    }
+}
@@ -0,0 +1,54 @@
+use super::{split_into_zones, MapGenerator, TileType, MAP_SIZE};
+use crate::{
+    dungeon_crawl::{GameData, GameRng},
+    world_map::Array2D,
+};
+use rand::Rng;
+
+/// Chance to knock down a remaining wall between two cells, so the maze has loops.
+const LOOP_CHANCE: f32 = 0.1;
+
+/// Maze made with a randomized depth-first search. Cells are on even coordinates
+/// and the walls between them on odd ones.
+pub struct Maze;
+impl MapGenerator for Maze {
+    fn generate(&self, _data: &GameData, rng: &mut GameRng) -> (Array2D<TileType>, usize) {
+        let mut tile_map = Array2D::<TileType>::with_elem(MAP_SIZE, MAP_SIZE, TileType::Dead);
+        let is_cell =
+            |x: i32, y: i32| (2..=MAP_SIZE - 3).contains(&x) && (2..=MAP_SIZE - 3).contains(&y);
+
+        let mut stack = vec![(2, 2)];
+        tile_map[[2, 2]] = TileType::Alive(0);
+        while let Some(&(x, y)) = stack.last() {
+            let unvisited: Vec<_> = [(0, 2), (2, 0), (-2, 0), (0, -2)]
+                .iter()
+                .map(|(i, j)| (x + i, y + j))
+                .filter(|&(i, j)| is_cell(i, j) && tile_map[[i, j]] == TileType::Dead)
+                .collect();
+
+            if unvisited.is_empty() {
+                stack.pop();
+                continue;
+            }
+
+            let (next_x, next_y) = unvisited[rng.0.gen_range(0..unvisited.len())];
+            tile_map[[(x + next_x) / 2, (y + next_y) / 2]] = TileType::Alive(0);
+            tile_map[[next_x, next_y]] = TileType::Alive(0);
+            stack.push((next_x, next_y));
+        }
+
+        for x in 2..MAP_SIZE - 2 {
+            for y in 2..MAP_SIZE - 2 {
+                // Walls between two horizontal or vertical cells
+                let between = (x % 2 == 1 && y % 2 == 0 && is_cell(x - 1, y) && is_cell(x + 1, y))
+                    || (x % 2 == 0 && y % 2 == 1 && is_cell(x, y - 1) && is_cell(x, y + 1));
+                if between && rng.0.gen::<f32>() < LOOP_CHANCE {
+                    tile_map[[x, y]] = TileType::Alive(0);
+                }
+            }
+        }
+
+        let zone_count = split_into_zones(&mut tile_map);
+        (tile_map, zone_count)
+    }
+}
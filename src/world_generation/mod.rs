@@ -1,10 +1,423 @@
+mod bsp;
 mod cellular_automata;
+mod drunkards_walk;
+mod maze;
 
+use crate::{
+    bundles::{EnemyBundle, ItemBundle, PlayerBundle, SpriteMaterials},
+    dungeon_crawl::{Boss, GameData, GameRng, Scheduler},
+    items::ItemTable,
+    monsters::MonsterTable,
+    world_map::{Array2D, GridPosition, TileFactory, WorldMap},
+    AppState,
+};
 use bevy::prelude::*;
+use rand::Rng;
+use std::collections::VecDeque;
 
 pub struct WorldGenerationPlugins;
 impl PluginGroup for WorldGenerationPlugins {
     fn build(&mut self, group: &mut bevy::app::PluginGroupBuilder) {
-        group.add(cellular_automata::CellularAutomataPlugin);
+        group.add(WorldGenerationPlugin);
+    }
+}
+
+pub struct WorldGenerationPlugin;
+impl Plugin for WorldGenerationPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_enter(AppState::WorldGeneration).with_system(generate_world.system()),
+        );
+    }
+}
+
+const MAP_SIZE: i32 = 40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileType {
+    Alive(usize),
+    Dead,
+}
+
+pub trait MapGenerator {
+    /// Returns a `MAP_SIZE` x `MAP_SIZE` map where floor tiles are marked with their spawn
+    /// zone (numbered from 1) and the number of zones. Only tiles between 2 and
+    /// `MAP_SIZE - 3` may be floor.
+    fn generate(&self, data: &GameData, rng: &mut GameRng) -> (Array2D<TileType>, usize);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapGeneratorKind {
+    CellularAutomata,
+    Bsp,
+    DrunkardsWalk,
+    Maze,
+}
+
+impl MapGeneratorKind {
+    pub fn generator(self) -> &'static dyn MapGenerator {
+        match self {
+            MapGeneratorKind::CellularAutomata => &cellular_automata::CellularAutomata,
+            MapGeneratorKind::Bsp => &bsp::Bsp,
+            MapGeneratorKind::DrunkardsWalk => &drunkards_walk::DrunkardsWalk,
+            MapGeneratorKind::Maze => &maze::Maze,
+        }
+    }
+}
+
+fn generate_world(
+    mut commands: Commands,
+    mut materials: SpriteMaterials,
+    mut app_state: ResMut<State<AppState>>,
+    data: Res<GameData>,
+    monsters: Res<MonsterTable>,
+    items: Res<ItemTable>,
+    mut rng: ResMut<GameRng>,
+) {
+    *rng = data.floor_rng();
+
+    let generator = data.floor_map_generator().generator();
+    let (tile_map, mut zone_entities) = loop {
+        let (tile_map, zone_count) = generator.generate(&data, &mut rng);
+        if zone_count < 5 {
+            continue;
+        }
+
+        break (
+            tile_map,
+            get_zone_entities(
+                &mut commands,
+                &mut materials,
+                &data,
+                &monsters,
+                &items,
+                &mut rng,
+                zone_count,
+            ),
+        );
+    };
+
+    let mut stairs = GridPosition { x: 1, y: 1 };
+    while tile_map[stairs] == TileType::Dead {
+        stairs = GridPosition {
+            x: rng.0.gen_range(1..MAP_SIZE - 2),
+            y: rng.0.gen_range(1..MAP_SIZE - 2),
+        };
+    }
+    let stairs = (data.floor < GameData::FINAL_FLOOR).then_some(stairs);
+    // The player arrives from the previous floor on the up stairs.
+    let player = zone_entities[0][0];
+    let mut up_stairs = None;
+
+    let mut entities = Array2D::with_size(MAP_SIZE + 20, MAP_SIZE + 20);
+    let tile_factory = TileFactory::new(&mut materials);
+    for x in 1..MAP_SIZE - 1 {
+        for y in 1..MAP_SIZE - 1 {
+            let mut tile = vec![];
+
+            if stairs == Some(GridPosition { x, y }) {
+                tile.push(tile_factory.stairs(&mut commands, x + 9, y + 9));
+            } else if let TileType::Alive(zone) = tile_map[[x, y]] {
+                // Zones start at 1 so we have to substract one
+                let entity = zone_entities.get_mut(zone - 1).and_then(|e| e.pop());
+
+                if entity == Some(player) && data.floor > 1 {
+                    up_stairs = Some(GridPosition { x: x + 9, y: y + 9 });
+                    tile.push(tile_factory.up_stairs(&mut commands, x + 9, y + 9));
+                } else {
+                    tile.push(tile_factory.floor(&mut commands, x + 9, y + 9));
+                }
+
+                if let Some(e) = entity {
+                    commands
+                        .entity(e)
+                        .insert(GridPosition { x: x + 9, y: y + 9 });
+                    tile.push(e);
+                }
+            } else {
+                // Show wall only if it's adjencent to a floor
+                'finish: for i in -1..=1i32 {
+                    for j in -1..=1i32 {
+                        if let TileType::Alive(_) = tile_map[[x + i, y + j]] {
+                            tile.push(tile_factory.wall(&mut commands, x + 9, y + 9));
+                            break 'finish;
+                        }
+                    }
+                }
+            };
+
+            entities[[x + 9, y + 9]] = tile;
+        }
+    }
+
+    // Despawn unused enemies
+    for e in zone_entities.iter().flatten() {
+        commands.entity(*e).despawn();
+    }
+
+    commands.insert_resource(WorldMap {
+        entities,
+        tile_factory,
+        tiles: Array2D::with_size(MAP_SIZE + 20, MAP_SIZE + 20),
+        stairs: stairs.map(|stairs| GridPosition {
+            x: stairs.x + 9,
+            y: stairs.y + 9,
+        }),
+        up_stairs,
+    });
+    commands.insert_resource(Scheduler::default());
+    app_state.set(AppState::DungeonCrawlEnter).unwrap();
+}
+
+fn get_zone_entities(
+    commands: &mut Commands,
+    materials: &mut SpriteMaterials,
+    data: &GameData,
+    monsters: &MonsterTable,
+    items: &ItemTable,
+    rng: &mut GameRng,
+    zone_count: usize,
+) -> Vec<Vec<Entity>> {
+    let mut entities = vec![vec![]; zone_count];
+    entities[0].push(
+        commands
+            .spawn_bundle(PlayerBundle::new(materials, data))
+            .id(),
+    );
+
+    for _ in 0..data.floor_enemy_count() {
+        let zone = rng.0.gen_range(1..zone_count);
+        let monster = monsters.random_for_floor(data.floor, rng);
+        entities[zone].push(
+            commands
+                .spawn_bundle(EnemyBundle::new(monster, materials))
+                .id(),
+        );
+    }
+
+    if data.floor == GameData::FINAL_FLOOR {
+        let zone = rng.0.gen_range(1..zone_count);
+        entities[zone].push(
+            commands
+                .spawn_bundle(EnemyBundle::new(monsters.boss(), materials))
+                .insert(Boss)
+                .id(),
+        );
+    }
+
+    for _ in 0..data.floor_item_count() {
+        let zone = rng.0.gen_range(1..zone_count);
+        let item = items.random_for_floor(data.floor, rng);
+        entities[zone].push(commands.spawn_bundle(ItemBundle::new(item, materials)).id());
+    }
+
+    entities
+}
+
+fn flood_fill(
+    map: &mut Array2D<TileType>,
+    pos: (i32, i32),
+    fill: TileType,
+    distance: Option<u32>,
+) -> u32 {
+    let mut tiles = VecDeque::new();
+    tiles.push_back((pos, 0));
+
+    let mut count = 0;
+
+    while !tiles.is_empty() {
+        let ((x, y), dist) = tiles.pop_front().unwrap();
+
+        if map[[x, y]] != TileType::Alive(0) {
+            continue;
+        }
+
+        map[[x, y]] = fill;
+        count += 1;
+
+        for i in -1..=1i32 {
+            for j in -1..=1i32 {
+                // No diagonals or the same tile
+                if (i != 0 && j != 0) || (i == 0 && j == 0) {
+                    continue;
+                }
+
+                let new = (x + i, y + j);
+
+                if map[new] == TileType::Alive(0) {
+                    if let Some(distance) = distance {
+                        if distance > dist {
+                            tiles.push_back((new, dist + 1));
+                        }
+                    } else {
+                        tiles.push_back((new, dist + 1));
+                    }
+                }
+            }
+        }
+    }
+
+    count
+}
+
+fn select_largest_cave(tile_map: &mut Array2D<TileType>) -> u32 {
+    let mut current_fill_number = 0;
+    let mut max_fill_number = 0;
+    let mut max_fill_count = 0;
+    for x in 2..MAP_SIZE - 2 {
+        for y in 2..MAP_SIZE - 2 {
+            if tile_map[[x, y]] == TileType::Alive(0) {
+                current_fill_number += 1;
+                let count =
+                    flood_fill(tile_map, (x, y), TileType::Alive(current_fill_number), None);
+
+                if max_fill_count < count {
+                    max_fill_count = count;
+                    max_fill_number = current_fill_number;
+                }
+            }
+        }
+    }
+
+    for x in 2..MAP_SIZE - 2 {
+        for y in 2..MAP_SIZE - 2 {
+            if let TileType::Alive(fill) = tile_map[[x, y]] {
+                if fill == 0 {
+                    continue;
+                } else if fill == max_fill_number {
+                    tile_map[[x, y]] = TileType::Alive(0);
+                } else {
+                    tile_map[[x, y]] = TileType::Dead;
+                }
+            }
+        }
+    }
+
+    max_fill_count
+}
+
+fn split_into_zones(tile_map: &mut Array2D<TileType>) -> usize {
+    let mut current_fill_number = 0;
+    for x in 2..MAP_SIZE - 2 {
+        for y in 2..MAP_SIZE - 2 {
+            if tile_map[[x, y]] == TileType::Alive(0) {
+                current_fill_number += 1;
+                flood_fill(
+                    tile_map,
+                    (x, y),
+                    TileType::Alive(current_fill_number),
+                    Some(10),
+                );
+            }
+        }
+    }
+    current_fill_number
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GENERATORS: [MapGeneratorKind; 4] = [
+        MapGeneratorKind::CellularAutomata,
+        MapGeneratorKind::Bsp,
+        MapGeneratorKind::DrunkardsWalk,
+        MapGeneratorKind::Maze,
+    ];
+
+    fn floor_tiles(tile_map: &Array2D<TileType>) -> Vec<(i32, i32, usize)> {
+        let mut tiles = vec![];
+        for x in 0..MAP_SIZE {
+            for y in 0..MAP_SIZE {
+                if let TileType::Alive(zone) = tile_map[[x, y]] {
+                    tiles.push((x, y, zone));
+                }
+            }
+        }
+        tiles
+    }
+
+    #[test]
+    fn generators_keep_floor_inside_the_border() {
+        for kind in GENERATORS {
+            for seed in 0..10 {
+                let data = GameData {
+                    seed,
+                    ..Default::default()
+                };
+                let (tile_map, _) = kind.generator().generate(&data, &mut data.floor_rng());
+                for (x, y, _) in floor_tiles(&tile_map) {
+                    assert!(
+                        (2..=MAP_SIZE - 3).contains(&x) && (2..=MAP_SIZE - 3).contains(&y),
+                        "{:?} dug ({}, {}) with seed {}",
+                        kind,
+                        x,
+                        y,
+                        seed
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn generators_number_every_zone() {
+        for kind in GENERATORS {
+            for seed in 0..10 {
+                let data = GameData {
+                    seed,
+                    ..Default::default()
+                };
+                let (tile_map, zone_count) =
+                    kind.generator().generate(&data, &mut data.floor_rng());
+                let tiles = floor_tiles(&tile_map);
+                for zone in 1..=zone_count {
+                    assert!(tiles.iter().any(|&(_, _, z)| z == zone));
+                }
+                assert!(tiles.iter().all(|&(_, _, z)| (1..=zone_count).contains(&z)));
+            }
+        }
+    }
+
+    #[test]
+    fn generators_connect_all_floor() {
+        for kind in GENERATORS {
+            for seed in 0..10 {
+                let data = GameData {
+                    seed,
+                    ..Default::default()
+                };
+                let (tile_map, _) = kind.generator().generate(&data, &mut data.floor_rng());
+                let tiles = floor_tiles(&tile_map);
+
+                // Flood fill without zones or distance limits
+                let mut map = Array2D::<TileType>::with_elem(MAP_SIZE, MAP_SIZE, TileType::Dead);
+                for &(x, y, _) in &tiles {
+                    map[[x, y]] = TileType::Alive(0);
+                }
+                let (x, y, _) = tiles[0];
+                let filled = flood_fill(&mut map, (x, y), TileType::Alive(1), None);
+                assert_eq!(
+                    filled as usize,
+                    tiles.len(),
+                    "{:?} with seed {}",
+                    kind,
+                    seed
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn the_same_seed_generates_the_same_map() {
+        for kind in GENERATORS {
+            let data = GameData {
+                seed: 42,
+                ..Default::default()
+            };
+            let first = floor_tiles(&kind.generator().generate(&data, &mut data.floor_rng()).0);
+            let second = floor_tiles(&kind.generator().generate(&data, &mut data.floor_rng()).0);
+            assert_eq!(first, second);
+        }
     }
 }
@@ -1,14 +1,20 @@
-use crate::dungeon_crawl::Name;
+use crate::{bundles::SpriteMaterials, dungeon_crawl::Name};
 use bevy::{math::ivec2, prelude::*};
 use bitflags::bitflags;
-use pathfinding::directed::astar;
-use std::ops::{Index, IndexMut};
-
+use pathfinding::directed::dijkstra;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    ops::{Index, IndexMut},
+};
+
+#[cfg(not(feature = "headless"))]
 pub struct Grid {
     pub cell_size: IVec2,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct GridPosition {
     pub x: i32,
     pub y: i32,
@@ -18,7 +24,7 @@ pub struct Tile;
 pub struct BlocksMovement;
 pub struct BlocksVision;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Array2D<T> {
     elems: Vec<Vec<T>>,
 }
@@ -156,41 +162,72 @@ pub struct WorldMap {
     pub tile_factory: TileFactory,
 
     pub tiles: Array2D<TileFlags>,
-    pub stairs: GridPosition,
+    /// There are no stairs down on the final floor.
+    pub stairs: Option<GridPosition>,
+    /// There are no up stairs on the first floor.
+    pub up_stairs: Option<GridPosition>,
 }
 
 impl WorldMap {
-    pub fn pathfind(
+    /// Costs of a step for paths and `DijkstraMap`, diagonals are about √2 times longer.
+    const STRAIGHT_COST: i32 = 10;
+    const DIAGONAL_COST: i32 = 14;
+
+    /// Tiles one step away, diagonals included. Like sight in `line_of_sight`, diagonal
+    /// steps can't squeeze between two walls.
+    pub fn neighbours(&self, GridPosition { x, y }: GridPosition) -> Vec<GridPosition> {
+        let mut neighbours = vec![];
+        for (i, j) in [
+            (0, 1),
+            (1, 0),
+            (-1, 0),
+            (0, -1),
+            (1, 1),
+            (1, -1),
+            (-1, 1),
+            (-1, -1),
+        ] {
+            if self.tiles.get(x + i, y + j).is_some()
+                && !(self.tiles[[x + i, y]] & self.tiles[[x, y + j]])
+                    .contains(TileFlags::BLOCKS_PATHFINDING)
+            {
+                neighbours.push(GridPosition { x: x + i, y: y + j });
+            }
+        }
+        neighbours
+    }
+
+    fn step_cost(from: GridPosition, to: GridPosition) -> i32 {
+        if from.x != to.x && from.y != to.y {
+            Self::DIAGONAL_COST
+        } else {
+            Self::STRAIGHT_COST
+        }
+    }
+
+    /// Shortest path from `start` to the closest tile where `goal` is true, going only
+    /// through tiles where `passable` is true. The path starts with `start`.
+    pub fn path_to_nearest(
         &self,
         start: GridPosition,
-        end: GridPosition,
-    ) -> Option<(Vec<GridPosition>, i32)> {
-        astar::astar(
+        goal: impl Fn(GridPosition) -> bool,
+        passable: impl Fn(GridPosition) -> bool,
+    ) -> Option<Vec<GridPosition>> {
+        dijkstra::dijkstra(
             &start,
-            |&GridPosition { x, y }| {
-                let mut v = vec![];
-                for (i, j) in [(0, 1), (1, 0), (-1, 0), (0, -1)] {
-                    if let Some(t) = self.tiles.get(x + i, y + j) {
-                        if !t.contains(TileFlags::BLOCKS_PATHFINDING) {
-                            let cost = if t.contains(TileFlags::BLOCKS_MOVEMENT) {
-                                5
-                            } else {
-                                1
-                            };
-                            v.push((GridPosition { x: x + i, y: y + j }, cost));
-                        }
-                    }
-                }
-                v
+            |&pos| {
+                self.neighbours(pos)
+                    .into_iter()
+                    .filter(|&next| passable(next) || goal(next))
+                    .map(|next| (next, Self::step_cost(pos, next)))
+                    .collect::<Vec<_>>()
             },
-            |&GridPosition { x, y }| {
-                f32::sqrt(((end.x - x).pow(2) + (end.y - y).pow(2)) as f32).floor() as i32
-            },
-            |&pos| pos == end,
+            |&pos| goal(pos),
         )
+        .map(|(path, _)| path)
     }
 
-    pub fn _line_of_sight(&self, start: GridPosition, end: GridPosition) -> bool {
+    pub fn line_of_sight(&self, start: GridPosition, end: GridPosition) -> bool {
         let mut previous = None;
         for (x, y) in line_drawing::Bresenham::new((start.x, start.y), (end.x, end.y)) {
             if let Some(&tile) = self.tiles.get(x, y) {
@@ -214,6 +251,100 @@ impl WorldMap {
     }
 }
 
+/// Distance from every tile to the closest of a few goals over the terrain. Walking downhill
+/// leads to a goal, and on an `inverted` map away from them. One map serves every creature
+/// heading for the same goals, creatures in the way are left to `downhill`.
+#[derive(Debug, Clone, Default)]
+pub struct DijkstraMap {
+    /// `None` for walls and tiles walled off from the goals.
+    distances: Array2D<Option<i32>>,
+}
+
+impl DijkstraMap {
+    /// Inverted distances are scaled by this before spreading them again, so a longer way
+    /// that ends far from the goals beats the closest dead end.
+    const FLEE_FACTOR: (i32, i32) = (6, 5);
+
+    pub fn new(world: &WorldMap, goals: &[GridPosition]) -> Self {
+        let size = world.tiles.size();
+        let mut distances = Array2D::with_elem(size.x, size.y, None);
+        for goal in goals {
+            distances[*goal] = Some(0);
+        }
+        Self::spread(world, distances)
+    }
+
+    /// A map for running away from the goals of this one.
+    pub fn inverted(&self, world: &WorldMap) -> Self {
+        let (num, den) = Self::FLEE_FACTOR;
+        let mut distances = self.distances.clone();
+        let size = distances.size();
+        for x in 0..size.x {
+            for y in 0..size.y {
+                distances[[x, y]] = distances[[x, y]].map(|d: i32| -d * num / den);
+            }
+        }
+        Self::spread(world, distances)
+    }
+
+    pub fn get(&self, position: GridPosition) -> Option<i32> {
+        self.distances
+            .get(position.x, position.y)
+            .copied()
+            .flatten()
+    }
+
+    /// The neighbour closest to a goal that can be entered, if it's any closer than `position`.
+    pub fn downhill(
+        &self,
+        world: &WorldMap,
+        position: GridPosition,
+        can_enter: impl Fn(GridPosition) -> bool,
+    ) -> Option<GridPosition> {
+        let mut best = (position, self.get(position)?);
+        for next in world.neighbours(position) {
+            match self.get(next) {
+                Some(distance) if distance < best.1 && can_enter(next) => best = (next, distance),
+                _ => {}
+            }
+        }
+        Some(best.0).filter(|best| *best != position)
+    }
+
+    /// Lowers every distance to the lowest one of its neighbours plus the step between them.
+    fn spread(world: &WorldMap, mut distances: Array2D<Option<i32>>) -> Self {
+        let mut queue = BinaryHeap::new();
+        let size = distances.size();
+        for x in 0..size.x {
+            for y in 0..size.y {
+                if let Some(distance) = distances[[x, y]] {
+                    queue.push(Reverse((distance, x, y)));
+                }
+            }
+        }
+
+        while let Some(Reverse((distance, x, y))) = queue.pop() {
+            if distances[[x, y]] != Some(distance) {
+                continue;
+            }
+            let position = GridPosition { x, y };
+            for next in world.neighbours(position) {
+                if world.tiles[next].contains(TileFlags::BLOCKS_PATHFINDING) {
+                    continue;
+                }
+                // Creatures walk from `next` to `position`.
+                let through = distance + WorldMap::step_cost(next, position);
+                if distances[next].is_none_or(|d| through < d) {
+                    distances[next] = Some(through);
+                    queue.push(Reverse((through, next.x, next.y)));
+                }
+            }
+        }
+
+        Self { distances }
+    }
+}
+
 pub struct TileFactory {
     pub visible_wall_material: Handle<ColorMaterial>,
     pub visible_floor_material: Handle<ColorMaterial>,
@@ -225,35 +356,14 @@ pub struct TileFactory {
 }
 
 impl TileFactory {
-    pub fn new(
-        asset_server: &Res<AssetServer>,
-        materials: &mut ResMut<Assets<ColorMaterial>>,
-    ) -> Self {
+    pub fn new(materials: &mut SpriteMaterials) -> Self {
         Self {
-            visible_wall_material: materials.add(ColorMaterial {
-                texture: Some(asset_server.load("brick-wall.png")),
-                color: Color::hex("826007").unwrap(),
-            }),
-            visible_floor_material: materials.add(ColorMaterial {
-                texture: Some(asset_server.load("square.png")),
-                color: Color::hex("826007").unwrap(),
-            }),
-            visible_stairs_material: materials.add(ColorMaterial {
-                texture: Some(asset_server.load("stairs.png")),
-                color: Color::hex("826007").unwrap(),
-            }),
-            explored_wall_material: materials.add(ColorMaterial {
-                texture: Some(asset_server.load("brick-wall.png")),
-                color: Color::hex("444444").unwrap(),
-            }),
-            explored_floor_material: materials.add(ColorMaterial {
-                texture: Some(asset_server.load("square.png")),
-                color: Color::hex("444444").unwrap(),
-            }),
-            explored_stairs_material: materials.add(ColorMaterial {
-                texture: Some(asset_server.load("stairs.png")),
-                color: Color::hex("444444").unwrap(),
-            }),
+            visible_wall_material: materials.get("brick-wall.png", Color::hex("826007").unwrap()),
+            visible_floor_material: materials.get("square.png", Color::hex("826007").unwrap()),
+            visible_stairs_material: materials.get("stairs.png", Color::hex("826007").unwrap()),
+            explored_wall_material: materials.get("brick-wall.png", Color::hex("444444").unwrap()),
+            explored_floor_material: materials.get("square.png", Color::hex("444444").unwrap()),
+            explored_stairs_material: materials.get("stairs.png", Color::hex("444444").unwrap()),
         }
     }
 
@@ -292,4 +402,111 @@ impl TileFactory {
             .insert_bundle((Tile, GridPosition { x, y }, Name(String::from("stairs"))))
             .id()
     }
+
+    pub fn up_stairs(&self, commands: &mut Commands, x: i32, y: i32) -> Entity {
+        commands
+            .spawn_bundle(SpriteBundle {
+                material: self.explored_stairs_material.clone(),
+                ..Default::default()
+            })
+            .insert_bundle((Tile, GridPosition { x, y }, Name(String::from("up stairs"))))
+            .id()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(x: i32, y: i32) -> GridPosition {
+        GridPosition { x, y }
+    }
+
+    /// A map from rows of `#` for walls and `.` for floor, the first row on top.
+    fn world(rows: &[&str]) -> WorldMap {
+        let height = rows.len() as i32;
+        let width = rows[0].len() as i32;
+        let mut tiles = Array2D::with_size(width, height);
+        for (y, row) in rows.iter().rev().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if c == '#' {
+                    tiles[[x as i32, y as i32]] = TileFlags::BLOCKS_MOVEMENT
+                        | TileFlags::BLOCKS_VISION
+                        | TileFlags::BLOCKS_PATHFINDING;
+                }
+            }
+        }
+        WorldMap {
+            entities: Array2D::with_size(width, height),
+            tile_factory: TileFactory {
+                visible_wall_material: Handle::default(),
+                visible_floor_material: Handle::default(),
+                visible_stairs_material: Handle::default(),
+                explored_wall_material: Handle::default(),
+                explored_floor_material: Handle::default(),
+                explored_stairs_material: Handle::default(),
+            },
+            tiles,
+            stairs: None,
+            up_stairs: None,
+        }
+    }
+
+    #[test]
+    fn distances_count_straight_and_diagonal_steps() {
+        let world = world(&["#####", "#...#", "#...#", "#...#", "#####"]);
+        let map = DijkstraMap::new(&world, &[pos(1, 1)]);
+        assert_eq!(map.get(pos(1, 1)), Some(0));
+        assert_eq!(map.get(pos(3, 1)), Some(20));
+        assert_eq!(map.get(pos(3, 3)), Some(28));
+        assert_eq!(map.get(pos(3, 2)), Some(24));
+        assert_eq!(map.get(pos(0, 0)), None);
+        assert_eq!(map.get(pos(9, 9)), None);
+    }
+
+    #[test]
+    fn walled_off_tiles_have_no_distance() {
+        let world = world(&["#######", "#..#..#", "#######"]);
+        let map = DijkstraMap::new(&world, &[pos(1, 1)]);
+        assert_eq!(map.get(pos(2, 1)), Some(10));
+        assert_eq!(map.get(pos(4, 1)), None);
+        assert_eq!(map.downhill(&world, pos(4, 1), |_| true), None);
+    }
+
+    #[test]
+    fn chasing_walks_around_walls_to_the_goal() {
+        let world = world(&["#####", "#...#", "#.#.#", "#.#.#", "#####"]);
+        let map = DijkstraMap::new(&world, &[pos(1, 1)]);
+        let mut position = pos(3, 1);
+        let mut steps = 0;
+        while let Some(next) = map.downhill(&world, position, |_| true) {
+            assert!(world.neighbours(position).contains(&next));
+            position = next;
+            steps += 1;
+        }
+        assert_eq!(position, pos(1, 1));
+        assert_eq!(steps, 4);
+    }
+
+    #[test]
+    fn downhill_skips_tiles_that_cannot_be_entered() {
+        let world = world(&["#####", "#...#", "#####"]);
+        let map = DijkstraMap::new(&world, &[pos(1, 1)]);
+        assert_eq!(map.downhill(&world, pos(3, 1), |_| true), Some(pos(2, 1)));
+        assert_eq!(map.downhill(&world, pos(3, 1), |p| p != pos(2, 1)), None);
+        assert_eq!(map.downhill(&world, pos(1, 1), |_| true), None);
+    }
+
+    #[test]
+    fn fleeing_walks_away_from_the_goal() {
+        let world = world(&["#########", "#.......#", "#########"]);
+        let chase = DijkstraMap::new(&world, &[pos(1, 1)]);
+        let flee = chase.inverted(&world);
+        let mut position = pos(3, 1);
+        while let Some(next) = flee.downhill(&world, position, |_| true) {
+            assert!(chase.get(next) > chase.get(position));
+            position = next;
+        }
+        assert_eq!(position, pos(7, 1));
+    }
 }